@@ -0,0 +1,14 @@
+fn main() {
+    println!("cargo:rerun-if-changed=build.rs");
+
+    // whisper_full_params changes layout between whisper.cpp releases, so it's
+    // only touched from C.
+    #[cfg(feature = "whisper")]
+    {
+        println!("cargo:rerun-if-changed=src/adapters/recognizer/whisper.c");
+        cc::Build::new()
+            .file("src/adapters/recognizer/whisper.c")
+            .compile("trascri_whisper");
+        println!("cargo:rustc-link-lib=whisper");
+    }
+}
@@ -1,29 +1,131 @@
 # Fetches the models from https://alphacephei.com/vosk/models and creates a json
-# file with names mapped to model urls.
+# file describing each model: name, url, language, version, variant, license
+# and download size, plus the speaker identification model. The whisper.cpp
+# models, hosted elsewhere, are listed by hand in WHISPER_MODELS.
+# With --checksums, every archive is downloaded to compute its sha256, which
+# the app uses to verify downloads.
 # ATTENTION: The output must be checked by hand before pushing the generated json
 
 import urllib.request
 import re
 import datetime
 import json
+import hashlib
+import sys
 with urllib.request.urlopen("https://alphacephei.com/vosk/models") as response:
    html = response.read()
 
+LANGUAGES = {
+    "ar": "Arabic", "ca": "Catalan", "cn": "Chinese", "cs": "Czech",
+    "de": "German", "en-in": "English (India)", "en-us": "English (US)",
+    "eo": "Esperanto", "es": "Spanish", "fa": "Persian", "fr": "French",
+    "hi": "Hindi", "it": "Italian", "ja": "Japanese", "ko": "Korean",
+    "kz": "Kazakh", "nl": "Dutch", "pl": "Polish", "pt": "Portuguese",
+    "ru": "Russian", "sv": "Swedish", "tr": "Turkish", "uk": "Ukrainian",
+    "vn": "Vietnamese",
+}
 
-def process_html(html):
+LICENSES = {"Apache 2.0": "Apache-2.0", "MIT": "MIT"}
+
+WHISPER_URL = "https://huggingface.co/ggerganov/whisper.cpp/resolve/main/ggml-{}.bin"
+# The weights were only released once, except the large ones, which aren't listed.
+WHISPER_VERSION = "1"
+# name, file suffix, language, variant, size in bytes
+WHISPER_MODELS = [
+    ("whisper-tiny", "tiny", "Multilingual, tiny", "small", 77691713),
+    ("whisper-base", "base", "Multilingual, base", "small", 147951465),
+    ("whisper-base-en", "base.en", "English, base", "small", 147964211),
+    ("whisper-small", "small", "Multilingual, small", "small", 487601967),
+    ("whisper-medium", "medium", "Multilingual, medium", "large", 1533763059),
+]
+
+def sha256_of_url(url):
+    h = hashlib.sha256()
+    with urllib.request.urlopen(url) as response:
+        while chunk := response.read(1024 * 1024):
+            h.update(chunk)
+    return h.hexdigest()
+
+# Sizes are listed like "40M" or "1.8G".
+def parse_size(s):
+    m = re.fullmatch(r'([\d.]+)([MG])', s.strip())
+    if not m:
+        return None
+    return int(float(m.group(1)) * {"M": 1024 ** 2, "G": 1024 ** 3}[m.group(2)])
+
+# Returns the text of the cells of the table row linking to `url`.
+def row_cells(decoded, url):
+    row = re.search(r'<tr>(?:(?!</tr>).)*' + re.escape(url) + r'.*?</tr>', decoded, re.S)
+    if not row:
+        return []
+    cells = re.findall(r'<td[^>]*>(.*?)</td>', row.group(0), re.S)
+    return [re.sub(r'<[^>]+>', '', c).strip() for c in cells]
+
+def describe(decoded, model, checksums):
+    cells = row_cells(decoded, model["url"])
+    if len(cells) >= 2 and parse_size(cells[1]):
+        model["size"] = parse_size(cells[1])
+    if cells and cells[-1] in LICENSES:
+        model["license"] = LICENSES[cells[-1]]
+    if checksums:
+        model["sha256"] = sha256_of_url(model["url"])
+    return model
+
+def process_html(html, checksums):
     decoded = html.decode()
     res = re.findall('https://alphacephei.com.*small.*.zip', decoded)
     re_state_code = re.compile(r'(small-)(.*)(-)')
+    re_version = re.compile(r'\d+(\.\d+)+')
     data = []
     for r in res:
         res2 = re_state_code.search(r)
-        data.append({"name": res2.group(2), "url": r})
+        name = res2.group(2)
+        version = re_version.search(r.split(name, 1)[1])
+        model = {
+            "name": name,
+            "url": r,
+            "language": LANGUAGES.get(name, name),
+            "version": version.group(0) if version else "",
+            "variant": "small",
+        }
+        data.append(describe(decoded, model, checksums))
     return data
 
+def whisper_models(checksums):
+    data = []
+    for name, suffix, language, variant, size in WHISPER_MODELS:
+        model = {
+            "name": name,
+            "url": WHISPER_URL.format(suffix),
+            "language": language,
+            "version": WHISPER_VERSION,
+            "variant": variant,
+            "size": size,
+            "engine": "whisper",
+            "license": "MIT",
+        }
+        if checksums:
+            model["sha256"] = sha256_of_url(model["url"])
+        data.append(model)
+    return data
+
+def process_speaker_model(html, checksums):
+    decoded = html.decode()
+    url = re.search(r'https://alphacephei.com[^"]*spk-[\d.]+\.zip', decoded).group(0)
+    model = {
+        "name": "spk",
+        "url": url,
+        "language": "Speaker identification",
+        "version": re.search(r'spk-([\d.]+)\.zip', url).group(1),
+        "variant": "small",
+    }
+    return describe(decoded, model, checksums)
+
 def print_json(obj):
     print(json.dumps(obj, indent=4, sort_keys=True))
 
+checksums = "--checksums" in sys.argv
 print_json({
-    "models": process_html(html)
+    "models": process_html(html, checksums) + whisper_models(checksums),
+    "speaker_model": process_speaker_model(html, checksums),
 })
-
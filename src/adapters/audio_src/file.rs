@@ -7,16 +7,25 @@ use crate::ports::audio_src::AudioSrc;
 pub struct File(gio::File);
 
 impl File {
-    pub async fn get_file() -> gio::File {
+    // Returns `None` if the dialog was cancelled.
+    pub async fn get_file(parent: Option<&impl IsA<gtk::Window>>) -> Option<gio::File> {
         let dialog = gtk::FileChooserNative::new(
             Some("Select an audio file"),
-            None::<&gtk::Window>,
+            parent,
             gtk::FileChooserAction::Open,
             None,
             None,
         );
-        dialog.run_future().await;
-        dialog.file().unwrap()
+        let filter = gtk::FileFilter::new();
+        filter.set_name(Some("Audio and video files"));
+        filter.add_mime_type("audio/*");
+        filter.add_mime_type("video/*");
+        dialog.add_filter(&filter);
+
+        if dialog.run_future().await != gtk::ResponseType::Accept {
+            return None;
+        }
+        dialog.file()
     }
 }
 
@@ -27,9 +36,62 @@ impl From<gio::File> for File {
 }
 
 impl AudioSrc for File {
+    // Decodes any container GStreamer can read. The decoded audio is converted
+    // and resampled to whatever format the downstream caps ask for.
     fn make_element(&self) -> gst::Element {
-        let element = gst::ElementFactory::make("filesrc").build().unwrap();
-        element.set_property("location", &self.0.uri());
-        element
+        let bin = gst::Bin::new(Some("file-src"));
+        let decodebin = gst::ElementFactory::make("uridecodebin")
+            .property("uri", self.0.uri())
+            .property("caps", gst::Caps::builder("audio/x-raw").build())
+            .build()
+            .unwrap();
+        // Only audio streams are decoded, the others are exposed as they are
+        // and left unlinked. Containers are demuxed to reach the audio, their
+        // caps can look like video ones, like "video/quicktime".
+        let demuxers: Vec<gst::ElementFactory> = gst::ElementFactory::factories_with_type(
+            gst::ElementFactoryType::DEMUXER,
+            gst::Rank::Marginal,
+        )
+        .collect();
+        decodebin.connect("autoplug-continue", false, move |values| {
+            let caps = values[2]
+                .get::<gst::Caps>()
+                .expect("autoplug-continue caps");
+            let is_audio = caps
+                .structure(0)
+                .map(|s| s.name().starts_with("audio/"))
+                .unwrap_or(false);
+            let is_container = demuxers.iter().any(|d| d.can_sink_any_caps(&caps));
+            Some((is_audio || is_container).to_value())
+        });
+        let convert = gst::ElementFactory::make("audioconvert").build().unwrap();
+        let resample = gst::ElementFactory::make("audioresample").build().unwrap();
+        bin.add_many(&[&decodebin, &convert, &resample]).unwrap();
+        convert.link(&resample).unwrap();
+
+        let src_pad =
+            gst::GhostPad::with_target(Some("src"), &resample.static_pad("src").unwrap()).unwrap();
+        bin.add_pad(&src_pad).unwrap();
+
+        let convert = convert.downgrade();
+        decodebin.connect_pad_added(move |_, pad| {
+            let Some(convert) = convert.upgrade() else {
+                return;
+            };
+            let is_audio = pad
+                .current_caps()
+                .and_then(|caps| {
+                    caps.structure(0)
+                        .map(|s| s.name().starts_with("audio/x-raw"))
+                })
+                .unwrap_or(false);
+            let sink_pad = convert.static_pad("sink").unwrap();
+            // Only the first audio stream is transcribed.
+            if is_audio && !sink_pad.is_linked() {
+                pad.link(&sink_pad).unwrap();
+            }
+        });
+
+        bin.upcast()
     }
 }
@@ -1,3 +1,4 @@
 pub mod audio_src;
 pub mod models_repo;
 pub mod recognizer;
+pub mod text_filter;
@@ -1,14 +1,26 @@
 use std::io::prelude::*;
 use std::rc::Rc;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::{fs, path};
+use std::time::{Duration, Instant};
+use std::{fmt, fs, path};
 
 use anyhow::Context;
 use cap_std::fs as cap_fs;
 use gtk::glib;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 const MODELS_DEF: &str = include_str!("../../data/models.json");
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(100);
+// Files every vosk model has, relative to the model folder.
+const REQUIRED_MODEL_FILES: &[&str] = &["am/final.mdl", "conf/mfcc.conf"];
+// Models ship either a static or a dynamic decoding graph.
+const MODEL_GRAPH_FILES: &[&str] = &["graph/HCLG.fst", "graph/HCLr.fst"];
+// Whisper models are a single file, kept in a folder like vosk ones.
+pub const GGML_MODEL_FILE: &str = "ggml-model.bin";
+// The "ggml" magic number, as stored in little endian.
+const GGML_MAGIC: &[u8] = b"lmgg";
 
 #[derive(Clone)]
 pub struct ModelsRepo {
@@ -20,13 +32,93 @@ pub struct ModelsRepo {
 #[derive(Serialize, Deserialize)]
 struct Models {
     models: Vec<RemoteModel>,
+    // Used to tell speakers apart, with any language.
+    speaker_model: RemoteModel,
 }
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Variant {
+    #[default]
+    Small,
+    Large,
+}
+
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Engine {
+    #[default]
+    Vosk,
+    Whisper,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct RemoteModel {
+    // Short code, also used as the name of the model folder.
     pub name: String,
     pub url: String,
+    // Human-readable, like "English (US)".
+    pub language: String,
+    #[serde(default)]
+    pub version: String,
+    #[serde(default)]
+    pub variant: Variant,
+    #[serde(default)]
+    pub engine: Engine,
+    // SPDX identifier.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub license: Option<String>,
+    // Of the download, in bytes.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub size: Option<u64>,
+    // Hex encoded SHA-256 of the archive. Downloads are verified when present.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub sha256: Option<String>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DownloadProgress {
+    pub read: u64,
+    // Size of the whole archive, if the server sent it.
+    pub total: Option<u64>,
+}
+
+impl DownloadProgress {
+    pub fn fraction(&self) -> Option<f64> {
+        self.total
+            .filter(|total| *total > 0)
+            .map(|total| self.read as f64 / total as f64)
+    }
+}
+
+enum DownloadEvent {
+    Progress(DownloadProgress),
+    Done(anyhow::Result<()>),
+}
+
+#[derive(Clone, Default)]
+pub struct CancelHandle(Arc<AtomicBool>);
+
+impl CancelHandle {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+    fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
 }
 
+// Error returned by a download stopped through its `CancelHandle`.
+#[derive(Debug)]
+pub struct Cancelled;
+
+impl fmt::Display for Cancelled {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "download cancelled")
+    }
+}
+
+impl std::error::Error for Cancelled {}
+
 impl ModelsRepo {
     pub fn from_path(path: path::PathBuf) -> Self {
         fs::DirBuilder::new().recursive(true).create(&path).unwrap();
@@ -39,20 +131,228 @@ impl ModelsRepo {
         }
     }
     pub fn model_path(&self, m: &RemoteModel) -> path::PathBuf {
-        self.path.join(&m.name)
+        self.local_model_path(&m.name)
+    }
+    pub fn local_model_path(&self, name: &str) -> path::PathBuf {
+        self.path.join(name)
+    }
+    // Installed models that aren't in the catalog, by folder name.
+    pub fn imported_models(&self) -> anyhow::Result<Vec<String>> {
+        let mut names = vec![];
+        for entry in self.dir.entries()? {
+            let entry = entry?;
+            let Ok(name) = entry.file_name().into_string() else {
+                continue;
+            };
+            if entry.file_type()?.is_dir()
+                && !Self::models_iter().any(|m| m.name == name)
+                && Self::speaker_model().name != name
+            {
+                names.push(name);
+            }
+        }
+        names.sort();
+        Ok(names)
+    }
+    // Folders of the downloaded and imported models of an engine.
+    pub fn installed_models(&self, engine: Engine) -> anyhow::Result<Vec<path::PathBuf>> {
+        let downloaded = Self::models_iter()
+            .filter(|m| self.is_downloaded(m))
+            .map(|m| self.model_path(&m));
+        let imported = self
+            .imported_models()?
+            .into_iter()
+            .map(|name| self.local_model_path(&name));
+        Ok(downloaded
+            .chain(imported)
+            .filter(|path| Self::engine_of(path) == engine)
+            .collect())
     }
+    pub fn remove_local(&self, name: &str) -> anyhow::Result<()> {
+        self.dir
+            .remove_dir_all(name)
+            .with_context(|| format!("couldn't remove the model {}", name))
+    }
+    // Sorted by engine, variant and language.
     pub fn models_iter() -> impl Iterator<Item = RemoteModel> {
-        let models: Models = serde_json::from_str(MODELS_DEF).unwrap();
+        let mut models: Models = serde_json::from_str(MODELS_DEF).unwrap();
+        models.models.sort_by(|a, b| {
+            (a.engine, a.variant, &a.language).cmp(&(b.engine, b.variant, &b.language))
+        });
         models.models.into_iter()
     }
+    // Tells apart the installed model folders by their contents.
+    pub fn engine_of(model_path: &path::Path) -> Engine {
+        if model_path.join(GGML_MODEL_FILE).is_file() {
+            Engine::Whisper
+        } else {
+            Engine::Vosk
+        }
+    }
+    // Guesses the language of an installed model from its folder name, like
+    // "en-us" or "vosk-model-small-it-0.22". Whisper models have none, most
+    // of them are multilingual.
+    pub fn language_code_of(model_path: &path::Path) -> Option<String> {
+        if Self::engine_of(model_path) == Engine::Whisper {
+            return None;
+        }
+        let name = model_path.file_name()?.to_str()?;
+        name.split('-')
+            .find(|part| part.len() == 2 && part.chars().all(|c| c.is_ascii_lowercase()))
+            .map(str::to_owned)
+    }
+    pub fn speaker_model() -> RemoteModel {
+        let models: Models = serde_json::from_str(MODELS_DEF).unwrap();
+        models.speaker_model
+    }
+    fn dir_size(dir: &cap_fs::Dir) -> std::io::Result<u64> {
+        let mut size = 0;
+        for entry in dir.entries()? {
+            let entry = entry?;
+            if entry.file_type()?.is_dir() {
+                size += Self::dir_size(&entry.open_dir()?)?;
+            } else {
+                size += entry.metadata()?.len();
+            }
+        }
+        Ok(size)
+    }
+    // Disk space used by an installed model, in bytes.
+    pub fn installed_size(&self, name: &str) -> anyhow::Result<u64> {
+        Ok(Self::dir_size(&self.dir.open_dir(name)?)?)
+    }
+    // Disk space used by the whole models folder, in bytes.
+    pub fn disk_usage(&self) -> anyhow::Result<u64> {
+        Ok(Self::dir_size(&self.dir)?)
+    }
     pub fn is_downloaded(&self, m: &RemoteModel) -> bool {
         self.dir.exists(&m.name)
     }
+    // Either may be missing, after an interrupted download or a removal
+    // from outside the app.
     pub fn remove(&self, m: &RemoteModel) -> anyhow::Result<()> {
-        self.dir.remove_dir_all(&m.name)?;
+        if self.dir.exists(&m.name) {
+            self.dir.remove_dir_all(&m.name)?;
+        }
+        let tmpf_path = format!("{}.tmp", &m.name);
+        if self.dir.exists(&tmpf_path) {
+            self.dir.remove_file(&tmpf_path)?;
+        }
         Ok(())
     }
 
+    // Checks that `dir` looks like a vosk model.
+    fn validate(dir: &cap_fs::Dir) -> anyhow::Result<()> {
+        for f in REQUIRED_MODEL_FILES {
+            if !dir.exists(f) {
+                anyhow::bail!("not a vosk model, {} is missing", f);
+            }
+        }
+        if !MODEL_GRAPH_FILES.iter().any(|f| dir.exists(f)) {
+            anyhow::bail!(
+                "not a vosk model, one of {} is missing",
+                MODEL_GRAPH_FILES.join(", ")
+            );
+        }
+        Ok(())
+    }
+    fn validate_ggml(f: &mut cap_fs::File) -> anyhow::Result<()> {
+        let mut magic = [0; 4];
+        f.read_exact(&mut magic).context("reading ggml model")?;
+        if magic != GGML_MAGIC {
+            anyhow::bail!("not a ggml model");
+        }
+        Ok(())
+    }
+    fn copy_dir(source: &cap_fs::Dir, dest: &cap_fs::Dir) -> anyhow::Result<()> {
+        for entry in source.entries()? {
+            let entry = entry?;
+            let name = entry.file_name();
+            if entry.file_type()?.is_dir() {
+                dest.create_dir(&name)?;
+                Self::copy_dir(&entry.open_dir()?, &dest.open_dir(&name)?)?;
+            } else {
+                let mut f = dest.open_with(
+                    &name,
+                    cap_std::fs::OpenOptions::new().write(true).create_new(true),
+                )?;
+                std::io::copy(&mut entry.open()?, &mut f)?;
+            }
+        }
+        Ok(())
+    }
+    fn install_local(dir: &cap_fs::Dir, source: &path::Path, name: &str) -> anyhow::Result<()> {
+        if source.extension() == Some("bin".as_ref()) {
+            let mut source_file = cap_fs::File::from_std(fs::File::open(source)?);
+            Self::validate_ggml(&mut source_file)?;
+            source_file.seek(std::io::SeekFrom::Start(0))?;
+            dir.create_dir(name)?;
+            let mut f = dir.open_dir(name)?.open_with(
+                GGML_MODEL_FILE,
+                cap_std::fs::OpenOptions::new().write(true).create_new(true),
+            )?;
+            std::io::copy(&mut source_file, &mut f).context("copying ggml model")?;
+            Ok(())
+        } else if source.is_dir() {
+            let source_dir = cap_fs::Dir::open_ambient_dir(source, cap_std::ambient_authority())?;
+            Self::validate(&source_dir)?;
+            dir.create_dir(name)?;
+            Self::copy_dir(&source_dir, &dir.open_dir(name)?).context("copying model folder")
+        } else {
+            let source_file = cap_fs::File::from_std(fs::File::open(source)?);
+            dir.create_dir(name)?;
+            Self::extract(source_file, dir.open_dir(name)?).context("extracting model archive")?;
+            Self::validate(&dir.open_dir(name)?)
+        }
+    }
+    // Installs a model from a local .zip archive, an unpacked model folder or a
+    // whisper .bin file, in a background thread. `on_done` receives the name of
+    // the installed model.
+    pub fn import(
+        &self,
+        source: path::PathBuf,
+        on_done: impl FnOnce(anyhow::Result<String>) + 'static,
+    ) {
+        let name = if source.is_dir() {
+            source.file_name()
+        } else {
+            source.file_stem()
+        };
+        let Some(name) = name.and_then(|n| n.to_str()).map(str::to_owned) else {
+            on_done(Err(anyhow::anyhow!("invalid model path {:?}", source)));
+            return;
+        };
+        if self.dir.exists(&name) {
+            on_done(Err(anyhow::anyhow!(
+                "a model named {:?} is already installed",
+                name
+            )));
+            return;
+        }
+
+        let (s, r) = glib::MainContext::channel(glib::PRIORITY_LOW);
+        let dir = self.dir.clone();
+        let thread_s = s.clone();
+        let pushed = self.thread_pool.push(move || {
+            let res = Self::install_local(&dir, &source, &name);
+            if res.is_err() && dir.exists(&name) {
+                let _ = dir.remove_dir_all(&name);
+            }
+            thread_s.send(res.map(|_| name)).unwrap();
+        });
+        if let Err(e) = pushed {
+            s.send(Err(e.into())).unwrap();
+        }
+
+        let mut on_done = Some(on_done);
+        r.attach(None, move |res| {
+            if let Some(on_done) = on_done.take() {
+                on_done(res);
+            }
+            glib::Continue(false)
+        });
+    }
+
     // uses cap_std to ensure the extraction is safe
     fn extract(source: cap_std::fs::File, dir: cap_std::fs::Dir) -> anyhow::Result<()> {
         let mut zip = zip::ZipArchive::new(source)?;
@@ -71,7 +371,7 @@ impl ModelsRepo {
                 .strip_prefix(&prefix)
                 .context("stripping root folder path prefix")?;
             if zf.is_dir() {
-                dir.create_dir(stripped_name).unwrap();
+                dir.create_dir(stripped_name)?;
             } else {
                 let mut f = dir.open_with(
                     stripped_name,
@@ -82,50 +382,203 @@ impl ModelsRepo {
         }
         Ok(())
     }
-    pub fn download(&self, m: &RemoteModel, cb: impl Fn() + 'static) {
-        let (s, r) = glib::MainContext::channel(glib::PRIORITY_LOW);
-
-        let url = m.url.to_owned();
+    fn sha256(f: &mut cap_fs::File) -> std::io::Result<String> {
+        f.seek(std::io::SeekFrom::Start(0))?;
+        let mut hasher = Sha256::new();
+        std::io::copy(f, &mut hasher)?;
+        Ok(format!("{:x}", hasher.finalize()))
+    }
+    // A partial `<name>.tmp` left by an interrupted download is resumed with
+    // an HTTP Range request. It's only deleted once it's known to be corrupt.
+    fn fetch(
+        dir: &cap_fs::Dir,
+        m: &RemoteModel,
+        cancel: &CancelHandle,
+        on_progress: impl Fn(DownloadProgress),
+    ) -> anyhow::Result<()> {
         let tmpf_path = format!("{}.tmp", &m.name);
-        let mut tmpf = self
-            .dir
+        let mut tmpf = dir
             .open_with(
                 &tmpf_path,
                 cap_std::fs::OpenOptions::new()
                     .write(true)
                     .read(true)
-                    .truncate(true)
                     .create(true),
             )
-            .unwrap();
-        let dir = self.dir.clone();
-        let name = m.name.to_owned();
-        self.thread_pool
-            .push(move || {
-                {
-                    let body = ureq::get(&url).call().unwrap();
-                    let mut reader = body.into_reader();
-                    std::io::copy(&mut reader, &mut tmpf).unwrap();
-                }
+            .context("opening temporary download file")?;
+        let existing = tmpf.seek(std::io::SeekFrom::End(0))?;
 
-                tmpf.seek(std::io::SeekFrom::Start(0)).unwrap();
+        let mut req = ureq::get(&m.url);
+        if existing > 0 {
+            req = req.set("Range", &format!("bytes={}-", existing));
+        }
+        let res = match req.call() {
+            Ok(res) => Some(res),
+            // The partial file already has every byte.
+            Err(ureq::Error::Status(416, _)) if existing > 0 => None,
+            Err(e) => return Err(e).context("requesting model"),
+        };
 
-                if !dir.exists(&name) {
-                    dir.create_dir(&name).unwrap();
+        if let Some(res) = res {
+            let content_len: Option<u64> = res
+                .header("Content-Length")
+                .and_then(|len| len.parse().ok());
+            let mut progress = if res.status() == 206 {
+                DownloadProgress {
+                    read: existing,
+                    total: content_len.map(|len| existing + len),
                 }
-                let dest_dir = dir.open_dir(&name).unwrap();
-                Self::extract(tmpf, dest_dir)
-                    .context("extracting downloaded model")
-                    .unwrap();
-                dir.remove_file(&tmpf_path).unwrap();
-
-                s.send(()).unwrap();
-            })
-            .unwrap();
+            } else {
+                // The server ignored the range, start over.
+                tmpf.set_len(0)?;
+                tmpf.seek(std::io::SeekFrom::Start(0))?;
+                DownloadProgress {
+                    read: 0,
+                    total: content_len,
+                }
+            };
+            let mut reader = res.into_reader();
+            let mut buf = vec![0; 64 * 1024];
+            let mut last_progress = Instant::now();
+            loop {
+                if cancel.is_cancelled() {
+                    return Err(Cancelled.into());
+                }
+                let n = reader.read(&mut buf).context("downloading model")?;
+                if n == 0 {
+                    break;
+                }
+                tmpf.write_all(&buf[..n])
+                    .context("writing temporary download file")?;
+                progress.read += n as u64;
+                if last_progress.elapsed() >= PROGRESS_INTERVAL {
+                    last_progress = Instant::now();
+                    on_progress(progress);
+                }
+            }
+            on_progress(progress);
 
-        r.attach(None, move |_| {
-            cb();
-            glib::Continue(false)
+            if let Some(total) = progress.total {
+                if progress.read < total {
+                    anyhow::bail!(
+                        "connection closed after {} of {} bytes, retry to resume",
+                        progress.read,
+                        total
+                    );
+                }
+            }
+        }
+        Self::install(dir, m, tmpf)
+    }
+    // Verifies the downloaded `<name>.tmp` before anything is extracted from
+    // it, then turns it into the model folder.
+    fn install(dir: &cap_fs::Dir, m: &RemoteModel, mut tmpf: cap_fs::File) -> anyhow::Result<()> {
+        let tmpf_path = format!("{}.tmp", &m.name);
+        if let Some(ref expected) = m.sha256 {
+            let actual = Self::sha256(&mut tmpf).context("computing checksum")?;
+            if !actual.eq_ignore_ascii_case(expected) {
+                dir.remove_file(&tmpf_path)?;
+                anyhow::bail!(
+                    "checksum mismatch, expected {} but got {}",
+                    expected,
+                    actual
+                );
+            }
+        }
+
+        tmpf.seek(std::io::SeekFrom::Start(0))?;
+
+        if m.engine == Engine::Whisper {
+            if let Err(e) = Self::validate_ggml(&mut tmpf) {
+                dir.remove_file(&tmpf_path)?;
+                return Err(e);
+            }
+            dir.create_dir(&m.name)?;
+            dir.rename(&tmpf_path, &dir.open_dir(&m.name)?, GGML_MODEL_FILE)?;
+            return Ok(());
+        }
+
+        if !dir.exists(&m.name) {
+            dir.create_dir(&m.name)?;
+        }
+        let dest_dir = dir.open_dir(&m.name)?;
+        if let Err(e) = Self::extract(tmpf, dest_dir) {
+            dir.remove_file(&tmpf_path)?;
+            return Err(e).context("extracting downloaded model");
+        }
+        dir.remove_file(&tmpf_path)?;
+        Ok(())
+    }
+    // Downloads and extracts the model in a background thread.
+    // The callbacks are called on the main context.
+    pub fn download(
+        &self,
+        m: &RemoteModel,
+        on_progress: impl Fn(DownloadProgress) + 'static,
+        on_done: impl FnOnce(anyhow::Result<()>) + 'static,
+    ) -> CancelHandle {
+        let (s, r) = glib::MainContext::channel(glib::PRIORITY_LOW);
+
+        let cancel = CancelHandle::default();
+        let dir = self.dir.clone();
+        let m = m.clone();
+        let thread_cancel = cancel.clone();
+        let thread_s = s.clone();
+        let pushed = self.thread_pool.push(move || {
+            let res = Self::fetch(&dir, &m, &thread_cancel, |p| {
+                thread_s.send(DownloadEvent::Progress(p)).unwrap();
+            });
+            // Never leave a half extracted model behind.
+            if res.is_err() && dir.exists(&m.name) {
+                let _ = dir.remove_dir_all(&m.name);
+            }
+            thread_s.send(DownloadEvent::Done(res)).unwrap();
+        });
+        if let Err(e) = pushed {
+            s.send(DownloadEvent::Done(Err(e.into()))).unwrap();
+        }
+
+        let mut on_done = Some(on_done);
+        r.attach(None, move |event| match event {
+            DownloadEvent::Progress(p) => {
+                on_progress(p);
+                glib::Continue(true)
+            }
+            DownloadEvent::Done(res) => {
+                if let Some(on_done) = on_done.take() {
+                    on_done(res);
+                }
+                glib::Continue(false)
+            }
         });
+        cancel
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn checksum_mismatch() {
+        let path = std::env::temp_dir().join(format!("trascri-models-{}", std::process::id()));
+        fs::create_dir_all(&path).unwrap();
+        let dir = cap_fs::Dir::open_ambient_dir(&path, cap_std::ambient_authority()).unwrap();
+        dir.write("en-us.tmp", b"not the archive").unwrap();
+        let m = RemoteModel {
+            name: "en-us".to_owned(),
+            sha256: Some(format!("{:x}", Sha256::digest(b"the archive"))),
+            ..ModelsRepo::speaker_model()
+        };
+
+        let tmpf = dir
+            .open_with("en-us.tmp", cap_fs::OpenOptions::new().read(true))
+            .unwrap();
+        let e = ModelsRepo::install(&dir, &m, tmpf).unwrap_err();
+        assert!(e.to_string().starts_with("checksum mismatch"), "{}", e);
+        // Nothing was extracted, and the corrupt archive isn't resumed.
+        assert!(!dir.exists("en-us"));
+        assert!(!dir.exists("en-us.tmp"));
+        fs::remove_dir_all(&path).unwrap();
     }
 }
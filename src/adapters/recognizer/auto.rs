@@ -0,0 +1,382 @@
+// Recognizer picking the model that fits the audio best, among several ones
+// for different languages. The first seconds of audio are decoded by every
+// candidate, the one recognizing them with the highest confidence decodes
+// them again and goes on with the rest of the stream.
+
+use std::path;
+
+use crate::ports::recognizer::*;
+
+// Audio decoded by every candidate, in seconds.
+const DETECTION_TIME: f32 = 5.0;
+
+struct OwnedWord {
+    confidence: f32,
+    start: f32,
+    end: f32,
+    text: String,
+}
+
+type Candidate<S> = (path::PathBuf, Box<dyn Recognizer<Sample = S> + Send>);
+
+pub struct Auto<S> {
+    // Identified by the path of their model.
+    candidates: Vec<Candidate<S>>,
+    winner: Option<usize>,
+    // The audio heard before picking the winner.
+    heard: Vec<S>,
+    // Samples of the silent windows dropped before the winner started, the
+    // times of its words are shifted by them.
+    skipped: usize,
+    // What the winner finalized while catching up with `heard` at the end
+    // of the stream, returned along with its final result.
+    flushed: Vec<OwnedWord>,
+    on_detected: Box<dyn FnMut(&path::Path) + Send>,
+}
+
+impl<S: Sample> Auto<S> {
+    // The candidates must share the same sample rate. `on_detected` is called
+    // with the winner of every recording.
+    pub fn new(
+        candidates: Vec<Candidate<S>>,
+        on_detected: impl FnMut(&path::Path) + Send + 'static,
+    ) -> Self {
+        assert!(!candidates.is_empty(), "no candidate models");
+        Self {
+            candidates,
+            winner: None,
+            heard: vec![],
+            skipped: 0,
+            flushed: vec![],
+            on_detected: Box::new(on_detected),
+        }
+    }
+    // Mean confidence of the words recognized in `audio`, `None` if there are none.
+    fn score(
+        recognizer: &mut (dyn Recognizer<Sample = S> + Send),
+        audio: &[S],
+    ) -> anyhow::Result<Option<f32>> {
+        let mut confidences = vec![];
+        if recognizer.feed(audio)? == DecodingState::Finalized {
+            confidences.extend(recognizer.result()?.words.iter().map(|w| w.confidence));
+        }
+        confidences.extend(
+            recognizer
+                .final_result()?
+                .words
+                .iter()
+                .map(|w| w.confidence),
+        );
+        recognizer.reset();
+        if confidences.is_empty() {
+            return Ok(None);
+        }
+        Ok(Some(
+            confidences.iter().sum::<f32>() / confidences.len() as f32,
+        ))
+    }
+    // Picks the winner from what was heard. Without any word recognized, it
+    // only picks one when `force` is set.
+    fn detect(&mut self, force: bool) -> anyhow::Result<Option<usize>> {
+        let mut best: Option<(usize, f32)> = None;
+        for (i, (_, recognizer)) in self.candidates.iter_mut().enumerate() {
+            let Some(score) = Self::score(recognizer.as_mut(), &self.heard)? else {
+                continue;
+            };
+            if !matches!(best, Some((_, best_score)) if best_score >= score) {
+                best = Some((i, score));
+            }
+        }
+        let winner = match best {
+            Some((i, _)) => i,
+            None if force => 0,
+            None => return Ok(None),
+        };
+        (self.on_detected)(&self.candidates[winner].0);
+        self.winner = Some(winner);
+        Ok(Some(winner))
+    }
+    fn winner_mut(&mut self) -> Option<&mut (dyn Recognizer<Sample = S> + Send)> {
+        let i = self.winner?;
+        Some(self.candidates[i].1.as_mut())
+    }
+    fn offset(&self) -> f32 {
+        self.skipped as f32 / self.sample_rate()
+    }
+}
+
+fn shift(words: &mut [Word<'_>], offset: f32) {
+    for w in words {
+        w.start += offset;
+        w.end += offset;
+    }
+}
+
+// Makes the times of the winner relative to the start of the stream.
+fn shifted(mut r: Recognized<'_>, offset: f32) -> Recognized<'_> {
+    shift(&mut r.words, offset);
+    for a in &mut r.alternatives {
+        shift(&mut a.words, offset);
+    }
+    r.start += offset;
+    r.end += offset;
+    r
+}
+
+impl<S: Sample> Recognizer for Auto<S> {
+    type Sample = S;
+    fn feed(&mut self, data: &[Self::Sample]) -> anyhow::Result<DecodingState> {
+        if let Some(winner) = self.winner_mut() {
+            return winner.feed(data);
+        }
+        self.heard.extend_from_slice(data);
+        if (self.heard.len() as f32) < DETECTION_TIME * self.sample_rate() {
+            return Ok(DecodingState::Running);
+        }
+        match self.detect(false)? {
+            Some(i) => {
+                let heard = std::mem::take(&mut self.heard);
+                self.candidates[i].1.feed(&heard)
+            }
+            // Silence doesn't tell languages apart, detection starts over.
+            None => {
+                self.skipped += self.heard.len();
+                self.heard.clear();
+                Ok(DecodingState::Running)
+            }
+        }
+    }
+    fn partial_result(&mut self) -> anyhow::Result<Recognized<'_>> {
+        let offset = self.offset();
+        match self.winner_mut() {
+            Some(winner) => Ok(shifted(winner.partial_result()?, offset)),
+            None => Ok(Recognized::new(vec![])),
+        }
+    }
+    fn result(&mut self) -> anyhow::Result<Recognized<'_>> {
+        let offset = self.offset();
+        match self.winner_mut() {
+            Some(winner) => Ok(shifted(winner.result()?, offset)),
+            None => Ok(Recognized::new(vec![])),
+        }
+    }
+    // A recording shorter than the detection time is decided on what there is.
+    fn final_result(&mut self) -> anyhow::Result<Recognized<'_>> {
+        self.flushed.clear();
+        let i = match self.winner {
+            Some(i) => i,
+            None => {
+                let i = self.detect(true)?.expect("forced detection");
+                let heard = std::mem::take(&mut self.heard);
+                if self.candidates[i].1.feed(&heard)? == DecodingState::Finalized {
+                    let r = self.candidates[i].1.result()?;
+                    self.flushed = r
+                        .words
+                        .iter()
+                        .map(|w| OwnedWord {
+                            confidence: w.confidence,
+                            start: w.start,
+                            end: w.end,
+                            text: w.text.to_owned(),
+                        })
+                        .collect();
+                }
+                i
+            }
+        };
+        let offset = self.offset();
+        let last = self.candidates[i].1.final_result()?;
+        if self.flushed.is_empty() {
+            return Ok(shifted(last, offset));
+        }
+        // The alternatives and the voiceprint only cover the last part.
+        let words = self
+            .flushed
+            .iter()
+            .map(|w| Word {
+                confidence: w.confidence,
+                start: w.start,
+                end: w.end,
+                text: &w.text,
+            })
+            .chain(last.words)
+            .collect();
+        Ok(shifted(Recognized::new(words), offset))
+    }
+    fn sample_rate(&self) -> f32 {
+        self.candidates[0].1.sample_rate()
+    }
+    // The one of the winner, once there is one.
+    fn language(&self) -> Option<&str> {
+        self.candidates[self.winner?].1.language()
+    }
+    // The next recording may be in another language.
+    fn reset(&mut self) {
+        for (_, recognizer) in &mut self.candidates {
+            recognizer.reset();
+        }
+        self.winner = None;
+        self.heard.clear();
+        self.skipped = 0;
+        self.flushed.clear();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+
+    const SAMPLE_RATE: f32 = 100.0;
+    const WINDOW: usize = (DETECTION_TIME * SAMPLE_RATE) as usize;
+
+    // Finalizes a word over the audio of every chunk that isn't silent.
+    struct Listener {
+        word: &'static str,
+        confidence: f32,
+        fed: usize,
+        result: Option<(f32, f32)>,
+    }
+
+    impl Recognizer for Listener {
+        type Sample = i16;
+        fn feed(&mut self, data: &[i16]) -> anyhow::Result<DecodingState> {
+            let offset = self.fed;
+            self.fed += data.len();
+            self.result = None;
+            let Some(first) = data.iter().position(|&s| s != 0) else {
+                return Ok(DecodingState::Running);
+            };
+            let last = data.iter().rposition(|&s| s != 0).unwrap();
+            self.result = Some((
+                (offset + first) as f32 / SAMPLE_RATE,
+                (offset + last + 1) as f32 / SAMPLE_RATE,
+            ));
+            Ok(DecodingState::Finalized)
+        }
+        fn partial_result(&mut self) -> anyhow::Result<Recognized<'_>> {
+            Ok(Recognized::new(vec![]))
+        }
+        fn result(&mut self) -> anyhow::Result<Recognized<'_>> {
+            let words = self.result.map(|(start, end)| Word {
+                confidence: self.confidence,
+                start,
+                end,
+                text: self.word,
+            });
+            Ok(Recognized::new(words.into_iter().collect()))
+        }
+        // Every word is finalized as soon as it's heard.
+        fn final_result(&mut self) -> anyhow::Result<Recognized<'_>> {
+            Ok(Recognized::new(vec![]))
+        }
+        fn sample_rate(&self) -> f32 {
+            SAMPLE_RATE
+        }
+        fn language(&self) -> Option<&str> {
+            None
+        }
+        fn reset(&mut self) {
+            self.fed = 0;
+            self.result = None;
+        }
+    }
+
+    // Detects between "ciao" and "hello", the latter being recognized with
+    // more confidence. Returns the models detected so far.
+    fn auto() -> (Auto<i16>, Arc<Mutex<Vec<path::PathBuf>>>) {
+        let candidates: Vec<Candidate<i16>> = [("it", "ciao", 0.4), ("en", "hello", 0.9)]
+            .into_iter()
+            .map(|(model, word, confidence)| {
+                let recognizer: Box<dyn Recognizer<Sample = i16> + Send> = Box::new(Listener {
+                    word,
+                    confidence,
+                    fed: 0,
+                    result: None,
+                });
+                (path::PathBuf::from(model), recognizer)
+            })
+            .collect();
+        let detected = Arc::new(Mutex::new(vec![]));
+        let d = detected.clone();
+        let auto = Auto::new(candidates, move |path| {
+            d.lock().unwrap().push(path.to_owned());
+        });
+        (auto, detected)
+    }
+
+    fn describe(r: Recognized<'_>) -> Vec<(String, f32, f32)> {
+        r.words
+            .iter()
+            .map(|w| (w.text.to_owned(), w.start, w.end))
+            .collect()
+    }
+
+    #[test]
+    fn picks_the_most_confident_candidate() {
+        let (mut auto, detected) = auto();
+        assert!(auto.feed(&[1; WINDOW - 1]).unwrap() == DecodingState::Running);
+        assert!(detected.lock().unwrap().is_empty());
+        assert!(auto.feed(&[1]).unwrap() == DecodingState::Finalized);
+        assert_eq!(*detected.lock().unwrap(), [path::PathBuf::from("en")]);
+        assert_eq!(
+            describe(auto.result().unwrap()),
+            [("hello".to_owned(), 0.0, 5.0)]
+        );
+    }
+
+    #[test]
+    fn silence_restarts_detection() {
+        let (mut auto, detected) = auto();
+        auto.feed(&[0; WINDOW]).unwrap();
+        assert!(detected.lock().unwrap().is_empty());
+        let mut audio = vec![0; WINDOW];
+        audio[WINDOW / 2..].fill(1);
+        assert!(auto.feed(&audio).unwrap() == DecodingState::Finalized);
+        assert_eq!(*detected.lock().unwrap(), [path::PathBuf::from("en")]);
+        // Times still count the silence that was dropped.
+        assert_eq!(
+            describe(auto.result().unwrap()),
+            [("hello".to_owned(), 7.5, 10.0)]
+        );
+    }
+
+    #[test]
+    fn short_recording() {
+        let (mut auto, detected) = auto();
+        assert!(auto.feed(&[1; WINDOW / 5]).unwrap() == DecodingState::Running);
+        assert_eq!(
+            describe(auto.final_result().unwrap()),
+            [("hello".to_owned(), 0.0, 1.0)]
+        );
+        assert_eq!(*detected.lock().unwrap(), [path::PathBuf::from("en")]);
+    }
+
+    #[test]
+    fn short_silent_recording() {
+        let (mut auto, detected) = auto();
+        auto.feed(&[0; WINDOW / 5]).unwrap();
+        assert!(auto.final_result().unwrap().words.is_empty());
+        // The first candidate is picked without any word to go by.
+        assert_eq!(*detected.lock().unwrap(), [path::PathBuf::from("it")]);
+    }
+
+    #[test]
+    fn reset_between_recordings() {
+        let (mut auto, detected) = auto();
+        auto.feed(&[0; WINDOW]).unwrap();
+        auto.feed(&[1; WINDOW]).unwrap();
+        auto.final_result().unwrap();
+        auto.reset();
+
+        assert!(auto.feed(&[1; WINDOW - 1]).unwrap() == DecodingState::Running);
+        assert_eq!(detected.lock().unwrap().len(), 1);
+        assert!(auto.feed(&[1]).unwrap() == DecodingState::Finalized);
+        assert_eq!(detected.lock().unwrap().len(), 2);
+        assert_eq!(
+            describe(auto.result().unwrap()),
+            [("hello".to_owned(), 0.0, 5.0)]
+        );
+    }
+}
@@ -1 +1,6 @@
+pub mod auto;
+#[cfg(test)]
+pub mod scripted;
 pub mod vosk;
+#[cfg(feature = "whisper")]
+pub mod whisper;
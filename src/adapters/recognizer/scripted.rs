@@ -0,0 +1,148 @@
+// Recognizer replaying a fixed script instead of listening, for tests. Every
+// `feed` with some audio plays the next step, whatever the audio is.
+
+use anyhow::bail;
+
+use crate::ports::recognizer::*;
+
+#[derive(Debug, Clone)]
+pub enum Step {
+    Partial(&'static str),
+    Final(&'static str),
+    // Decoding fails.
+    Fail,
+}
+
+struct OwnedWord {
+    text: String,
+    start: f32,
+    end: f32,
+}
+
+pub struct Scripted {
+    script: Vec<Step>,
+    next: usize,
+    sample_rate: f32,
+    // Of every word.
+    confidence: f32,
+    language: Option<&'static str>,
+    // Samples fed since the last reset.
+    fed: usize,
+    // Samples fed when the current utterance started.
+    utterance_start: usize,
+    partial: Vec<OwnedWord>,
+    result: Vec<OwnedWord>,
+}
+
+impl Scripted {
+    pub fn new(script: Vec<Step>, sample_rate: f32) -> Self {
+        Self {
+            script,
+            next: 0,
+            sample_rate,
+            confidence: 1.0,
+            language: None,
+            fed: 0,
+            utterance_start: 0,
+            partial: vec![],
+            result: vec![],
+        }
+    }
+    pub fn with_confidence(self, confidence: f32) -> Self {
+        Self { confidence, ..self }
+    }
+    pub fn with_language(self, language: &'static str) -> Self {
+        Self {
+            language: Some(language),
+            ..self
+        }
+    }
+    // The words are spread evenly over the audio of the utterance.
+    fn words(&self, text: &str) -> Vec<OwnedWord> {
+        let start = self.utterance_start as f32 / self.sample_rate;
+        let end = self.fed as f32 / self.sample_rate;
+        let texts: Vec<&str> = text.split_whitespace().collect();
+        let len = (end - start) / texts.len().max(1) as f32;
+        texts
+            .into_iter()
+            .enumerate()
+            .map(|(i, text)| OwnedWord {
+                text: text.to_owned(),
+                start: start + i as f32 * len,
+                end: start + (i + 1) as f32 * len,
+            })
+            .collect()
+    }
+    fn finalize(&mut self, words: Vec<OwnedWord>) {
+        self.result = words;
+        self.partial.clear();
+        self.utterance_start = self.fed;
+    }
+}
+
+fn recognized(words: &[OwnedWord], confidence: f32) -> Recognized<'_> {
+    Recognized::new(
+        words
+            .iter()
+            .map(|w| Word {
+                confidence,
+                start: w.start,
+                end: w.end,
+                text: &w.text,
+            })
+            .collect(),
+    )
+}
+
+impl Recognizer for Scripted {
+    type Sample = i16;
+    // Once the script is over, the last partial result is kept.
+    fn feed(&mut self, data: &[Self::Sample]) -> anyhow::Result<DecodingState> {
+        if data.is_empty() {
+            return Ok(DecodingState::Running);
+        }
+        self.fed += data.len();
+        let Some(step) = self.script.get(self.next).cloned() else {
+            return Ok(DecodingState::Running);
+        };
+        self.next += 1;
+        match step {
+            Step::Partial(text) => {
+                self.partial = self.words(text);
+                Ok(DecodingState::Running)
+            }
+            Step::Final(text) => {
+                let words = self.words(text);
+                self.finalize(words);
+                Ok(DecodingState::Finalized)
+            }
+            Step::Fail => bail!("scripted failure"),
+        }
+    }
+    fn partial_result(&mut self) -> anyhow::Result<Recognized<'_>> {
+        Ok(recognized(&self.partial, self.confidence))
+    }
+    fn result(&mut self) -> anyhow::Result<Recognized<'_>> {
+        Ok(recognized(&self.result, self.confidence))
+    }
+    // Like the real recognizers, the pending partial result becomes final.
+    fn final_result(&mut self) -> anyhow::Result<Recognized<'_>> {
+        let words = std::mem::take(&mut self.partial);
+        self.finalize(words);
+        self.result()
+    }
+    fn sample_rate(&self) -> f32 {
+        self.sample_rate
+    }
+    fn language(&self) -> Option<&str> {
+        self.language
+    }
+    // The script starts over.
+    fn reset(&mut self) {
+        self.next = 0;
+        self.fed = 0;
+        self.utterance_start = 0;
+        self.partial.clear();
+        self.result.clear();
+    }
+}
@@ -1,57 +1,231 @@
+use std::collections::HashMap;
 use std::path;
+use std::sync::{Arc, Mutex, OnceLock};
 
-use anyhow::Context;
+use anyhow::{bail, Context};
 
+use crate::adapters::models_repo::ModelsRepo;
 use crate::ports::recognizer::*;
 
-pub struct Vosk(vosk::Recognizer, f32);
+// Voiceprints of shorter utterances are too unreliable to tell speakers apart.
+// Frames are 10ms long.
+const MIN_SPEAKER_FRAMES: u16 = 100;
+// Stands for speech outside of the phrase list, it's left out of the results.
+const UNKNOWN_WORD: &str = "[unk]";
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    // When greater than zero, finalized results also carry up to this many
+    // competing hypotheses.
+    pub max_alternatives: u16,
+    // With a speaker model, finalized results carry a voiceprint of the
+    // speaker instead of the alternatives, as vosk can't return both.
+    pub speaker_model_path: Option<path::PathBuf>,
+    // Phrase lists only work with models shipping a dynamic graph, which
+    // includes all the small ones. Other models ignore it.
+    pub vocabulary: Vocabulary,
+}
+
+// A model slot is locked while the model loads, so that whoever else needs
+// the same model waits for it instead of loading it again.
+type Slot<T> = Arc<Mutex<Option<Arc<T>>>>;
+
+fn get_or_load<T>(
+    slots: &Mutex<HashMap<path::PathBuf, Slot<T>>>,
+    path: &path::Path,
+    load: impl FnOnce() -> anyhow::Result<T>,
+) -> anyhow::Result<Arc<T>> {
+    let slot = slots
+        .lock()
+        .unwrap()
+        .entry(path.to_owned())
+        .or_default()
+        .clone();
+    let mut slot = slot.lock().unwrap();
+    if let Some(ref model) = *slot {
+        return Ok(model.clone());
+    }
+    let model = Arc::new(load()?);
+    *slot = Some(model.clone());
+    Ok(model)
+}
+
+// Loaded models, keyed by path. Loading a big model takes seconds, creating a
+// recognizer from a loaded one is cheap.
+#[derive(Default)]
+pub struct ModelCache {
+    models: Mutex<HashMap<path::PathBuf, Slot<vosk::Model>>>,
+    speaker_models: Mutex<HashMap<path::PathBuf, Slot<vosk::SpeakerModel>>>,
+}
+
+impl ModelCache {
+    pub fn global() -> &'static Self {
+        static CACHE: OnceLock<ModelCache> = OnceLock::new();
+        CACHE.get_or_init(Self::default)
+    }
+    pub fn model(&self, path: &path::Path) -> anyhow::Result<Arc<vosk::Model>> {
+        get_or_load(&self.models, path, || {
+            if !path.is_dir() {
+                bail!("model folder {} doesn't exist", path.display());
+            }
+            // Vosk only logs why a model can't be loaded.
+            vosk::Model::new(path.to_str().context("model path isn't utf-8")?)
+                .with_context(|| format!("couldn't load the vosk model in {}", path.display()))
+        })
+    }
+    pub fn speaker_model(&self, path: &path::Path) -> anyhow::Result<Arc<vosk::SpeakerModel>> {
+        get_or_load(&self.speaker_models, path, || {
+            vosk::SpeakerModel::new(path.to_str().context("speaker model path isn't utf-8")?)
+                .with_context(|| format!("couldn't load the speaker model in {}", path.display()))
+        })
+    }
+    // Loads everything a recognizer with these options needs.
+    pub fn preload(&self, model_path: &path::Path, opts: &Options) -> anyhow::Result<()> {
+        self.model(model_path)?;
+        if let Some(ref path) = opts.speaker_model_path {
+            self.speaker_model(path)?;
+        }
+        Ok(())
+    }
+    // Recognizers keep working, vosk models are reference counted.
+    pub fn forget(&self, path: &path::Path) {
+        self.models.lock().unwrap().remove(path);
+    }
+}
+
+// The recognizer, its sample rate and the language of its model.
+pub struct Vosk(vosk::Recognizer, f32, Option<String>);
 
 impl Vosk {
-    pub fn new(model_path: &path::Path, sample_rate: f32) -> Self {
-        let model = vosk::Model::new(model_path.to_str().unwrap()).unwrap();
-        let mut recognizer = vosk::Recognizer::new(&model, sample_rate).unwrap();
-        recognizer.set_max_alternatives(0);
+    // Models are loaded through the global `ModelCache`.
+    pub fn new(model_path: &path::Path, sample_rate: f32, opts: &Options) -> anyhow::Result<Self> {
+        let cache = ModelCache::global();
+        let model = cache.model(model_path)?;
+        let recognizer = match opts.vocabulary {
+            Vocabulary::Open => vosk::Recognizer::new(&model, sample_rate),
+            Vocabulary::Phrases(ref phrases) => {
+                // The grammar is a json list built by vosk without any escaping.
+                let mut grammar: Vec<String> = phrases
+                    .iter()
+                    .map(|p| p.replace(['"', '\\'], "").to_lowercase())
+                    .collect();
+                // Anything else is recognized as unknown, instead of being
+                // forced into one of the phrases.
+                grammar.push(UNKNOWN_WORD.to_owned());
+                vosk::Recognizer::new_with_grammar(&model, sample_rate, &grammar)
+            }
+        };
+        let mut recognizer = recognizer.context("couldn't create the vosk recognizer")?;
+        match opts.speaker_model_path {
+            Some(ref path) => {
+                let speaker_model = cache.speaker_model(path)?;
+                recognizer.set_speaker_model(&speaker_model);
+                recognizer.set_max_alternatives(0);
+            }
+            None => recognizer.set_max_alternatives(opts.max_alternatives),
+        }
         recognizer.set_words(true);
         recognizer.set_partial_words(true);
-        Self(recognizer, sample_rate)
+        Ok(Self(
+            recognizer,
+            sample_rate,
+            ModelsRepo::language_code_of(model_path),
+        ))
+    }
+}
+
+impl<'a> From<vosk::Word<'a>> for Word<'a> {
+    fn from(w: vosk::Word<'a>) -> Self {
+        Self {
+            confidence: w.conf,
+            start: w.start,
+            end: w.end,
+            text: w.word,
+        }
+    }
+}
+
+// In n-best mode vosk only scores whole hypotheses, so the confidence of a
+// word is estimated as the share of hypotheses agreeing on it.
+fn agreement(word: &vosk::WordInAlternative, alternatives: &[vosk::Alternative]) -> f32 {
+    let agreeing = alternatives
+        .iter()
+        .filter(|a| {
+            a.result
+                .iter()
+                .any(|w| w.word == word.word && w.start < word.end && w.end > word.start)
+        })
+        .count();
+    agreeing as f32 / alternatives.len() as f32
+}
+
+fn recognized(r: vosk::CompleteResult<'_>) -> anyhow::Result<Recognized<'_>> {
+    match r {
+        vosk::CompleteResult::Single(r) => {
+            let words = r
+                .result
+                .into_iter()
+                .filter(|w| w.word != UNKNOWN_WORD)
+                .map(Word::from);
+            let speaker = r
+                .speaker_info
+                .filter(|s| s.frames >= MIN_SPEAKER_FRAMES)
+                .map(|s| s.vector);
+            Ok(Recognized::new(words.collect()).with_speaker(speaker))
+        }
+        vosk::CompleteResult::Multiple(r) => {
+            let mut alternatives = r.alternatives.iter().map(|a| Alternative {
+                score: a.confidence,
+                words: a
+                    .result
+                    .iter()
+                    .filter(|w| w.word != UNKNOWN_WORD)
+                    .map(|w| Word {
+                        confidence: agreement(w, &r.alternatives),
+                        start: w.start,
+                        end: w.end,
+                        text: w.word,
+                    })
+                    .collect(),
+            });
+            let best = alternatives
+                .next()
+                .context("vosk returned no alternatives")?;
+            Ok(Recognized::new(best.words).with_alternatives(alternatives.collect()))
+        }
     }
 }
 
 impl Recognizer for Vosk {
     type Sample = i16;
-    fn feed(&mut self, data: &[Self::Sample]) -> DecodingState {
+    fn feed(&mut self, data: &[Self::Sample]) -> anyhow::Result<DecodingState> {
         match self.0.accept_waveform(data) {
-            vosk::DecodingState::Finalized => DecodingState::Finalized,
-            vosk::DecodingState::Running => DecodingState::Running,
-            vosk::DecodingState::Failed => panic!("Decoding failed"),
+            vosk::DecodingState::Finalized => Ok(DecodingState::Finalized),
+            vosk::DecodingState::Running => Ok(DecodingState::Running),
+            vosk::DecodingState::Failed => bail!("vosk failed to decode the audio"),
         }
     }
-    fn result(&mut self) -> anyhow::Result<Recognized> {
-        let r = self.0.result();
-        let r = r
-            .single()
-            .context("extracting final vosk recognizer result")?;
-        let words = r.result.into_iter().map(|w| Word {
-            confidence: w.conf,
-            text: w.word,
-        });
-        Ok(Recognized {
-            words: words.collect(),
-        })
+    fn result(&mut self) -> anyhow::Result<Recognized<'_>> {
+        recognized(self.0.result()).context("extracting vosk recognizer result")
     }
-    fn partial_result(&mut self) -> anyhow::Result<Recognized> {
+    fn final_result(&mut self) -> anyhow::Result<Recognized<'_>> {
+        recognized(self.0.final_result()).context("extracting final vosk recognizer result")
+    }
+    fn partial_result(&mut self) -> anyhow::Result<Recognized<'_>> {
         let r = self.0.partial_result();
-        let words = r.partial_result.into_iter().map(|w| Word {
-            confidence: w.conf,
-            text: w.word,
-        });
-        Ok(Recognized {
-            words: words.collect(),
-        })
+        let words = r
+            .partial_result
+            .into_iter()
+            .filter(|w| w.word != UNKNOWN_WORD)
+            .map(Word::from);
+        Ok(Recognized::new(words.collect()))
     }
     fn sample_rate(&self) -> f32 {
         self.1
     }
+    fn language(&self) -> Option<&str> {
+        self.2.as_deref()
+    }
     fn reset(&mut self) {
         self.0.reset();
     }
@@ -0,0 +1,33 @@
+// The parts of the whisper.cpp API that depend on the layout of its structs.
+
+#include <stdint.h>
+#include <whisper.h>
+
+struct whisper_context *trascri_whisper_init(const char *path) {
+    return whisper_init_from_file_with_params(path, whisper_context_default_params());
+}
+
+int trascri_whisper_full(struct whisper_context *ctx, const float *samples, int n_samples,
+                         const char *language, int n_threads) {
+    struct whisper_full_params params = whisper_full_default_params(WHISPER_SAMPLING_GREEDY);
+    params.n_threads = n_threads;
+    params.language = language;
+    // Every window is decoded on its own, previous text would only be
+    // repeated over and over on silence.
+    params.no_context = true;
+    params.token_timestamps = true;
+    params.suppress_blank = true;
+    params.print_progress = false;
+    params.print_realtime = false;
+    params.print_special = false;
+    params.print_timestamps = false;
+    return whisper_full(ctx, params, samples, n_samples);
+}
+
+// Times are in centiseconds from the start of the decoded samples.
+void trascri_whisper_token_times(struct whisper_context *ctx, int segment, int token,
+                                 int64_t *t0, int64_t *t1) {
+    whisper_token_data data = whisper_full_get_token_data(ctx, segment, token);
+    *t0 = data.t0;
+    *t1 = data.t1;
+}
@@ -0,0 +1,314 @@
+// Recognizer backed by whisper.cpp. Whisper decodes windows of up to 30
+// seconds at once, so audio is buffered and decoded again every few seconds
+// for partial results. A window is finalized when the speaker pauses or when
+// it's full.
+
+use std::ffi::{CStr, CString};
+use std::os::raw::{c_char, c_float, c_int};
+use std::path;
+use std::ptr::NonNull;
+
+use anyhow::{bail, Context};
+
+use crate::ports::recognizer::*;
+
+mod ffi {
+    use super::*;
+
+    #[repr(C)]
+    pub struct WhisperContext {
+        _private: [u8; 0],
+    }
+
+    #[link(name = "whisper")]
+    extern "C" {
+        pub fn whisper_free(ctx: *mut WhisperContext);
+        pub fn whisper_token_eot(ctx: *mut WhisperContext) -> c_int;
+        pub fn whisper_full_n_segments(ctx: *mut WhisperContext) -> c_int;
+        pub fn whisper_full_get_segment_t0(ctx: *mut WhisperContext, segment: c_int) -> i64;
+        pub fn whisper_full_n_tokens(ctx: *mut WhisperContext, segment: c_int) -> c_int;
+        pub fn whisper_full_get_token_text(
+            ctx: *mut WhisperContext,
+            segment: c_int,
+            token: c_int,
+        ) -> *const c_char;
+        pub fn whisper_full_get_token_id(
+            ctx: *mut WhisperContext,
+            segment: c_int,
+            token: c_int,
+        ) -> c_int;
+        pub fn whisper_full_get_token_p(
+            ctx: *mut WhisperContext,
+            segment: c_int,
+            token: c_int,
+        ) -> c_float;
+        pub fn whisper_full_lang_id(ctx: *mut WhisperContext) -> c_int;
+        pub fn whisper_lang_str(id: c_int) -> *const c_char;
+    }
+
+    // Defined in whisper.c
+    extern "C" {
+        pub fn trascri_whisper_init(path: *const c_char) -> *mut WhisperContext;
+        pub fn trascri_whisper_full(
+            ctx: *mut WhisperContext,
+            samples: *const c_float,
+            n_samples: c_int,
+            language: *const c_char,
+            n_threads: c_int,
+        ) -> c_int;
+        pub fn trascri_whisper_token_times(
+            ctx: *mut WhisperContext,
+            segment: c_int,
+            token: c_int,
+            t0: *mut i64,
+            t1: *mut i64,
+        );
+    }
+}
+
+// Whisper models only take 16kHz audio.
+const SAMPLE_RATE: usize = 16000;
+const WINDOW: usize = 30 * SAMPLE_RATE;
+// How much new audio triggers a new partial result.
+const STEP: usize = 3 * SAMPLE_RATE;
+// A pause this long ends the window.
+const PAUSE: usize = SAMPLE_RATE * 8 / 10;
+const SILENCE_RMS: f32 = 0.01;
+
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    // Like "en". Detected on every window when `None`.
+    pub language: Option<String>,
+}
+
+struct OwnedWord {
+    text: String,
+    confidence: f32,
+    start: f32,
+    end: f32,
+}
+
+impl OwnedWord {
+    fn as_word(&self) -> Word<'_> {
+        Word {
+            confidence: self.confidence,
+            start: self.start,
+            end: self.end,
+            text: &self.text,
+        }
+    }
+}
+
+struct DecodedSegment {
+    // Offset in the window, in samples.
+    start: usize,
+    words: Vec<OwnedWord>,
+}
+
+fn is_silent(samples: &[f32]) -> bool {
+    samples.chunks(SAMPLE_RATE / 10).all(|chunk| {
+        let sum: f32 = chunk.iter().map(|s| s * s).sum();
+        (sum / chunk.len() as f32).sqrt() < SILENCE_RMS
+    })
+}
+
+pub struct Whisper {
+    ctx: NonNull<ffi::WhisperContext>,
+    language: CString,
+    // The one asked for, or the one detected in the last window decoded.
+    recognized_language: Option<String>,
+    n_threads: c_int,
+    // Audio not finalized yet, starting `window_start` seconds into the stream.
+    window: Vec<f32>,
+    window_start: f32,
+    // Samples added to the window since it was last decoded.
+    undecoded: usize,
+    partial: Vec<OwnedWord>,
+    result: Vec<OwnedWord>,
+}
+
+// whisper.cpp contexts can be used from any thread, one at a time.
+unsafe impl Send for Whisper {}
+
+impl Whisper {
+    // `model_path` is a ggml model file.
+    pub fn new(model_path: &path::Path, opts: &Options) -> anyhow::Result<Self> {
+        let path = CString::new(model_path.to_str().context("model path isn't utf-8")?)?;
+        let ctx = NonNull::new(unsafe { ffi::trascri_whisper_init(path.as_ptr()) })
+            .with_context(|| format!("loading whisper model {}", model_path.display()))?;
+        let n_threads = std::thread::available_parallelism().map_or(4, |n| n.get().min(8));
+        Ok(Self {
+            ctx,
+            language: CString::new(opts.language.as_deref().unwrap_or("auto"))?,
+            recognized_language: opts.language.clone(),
+            n_threads: n_threads as c_int,
+            window: vec![],
+            window_start: 0.0,
+            undecoded: 0,
+            partial: vec![],
+            result: vec![],
+        })
+    }
+    fn decode(&mut self) -> anyhow::Result<Vec<DecodedSegment>> {
+        self.undecoded = 0;
+        let ctx = self.ctx.as_ptr();
+        let ret = unsafe {
+            ffi::trascri_whisper_full(
+                ctx,
+                self.window.as_ptr(),
+                self.window.len() as c_int,
+                self.language.as_ptr(),
+                self.n_threads,
+            )
+        };
+        if ret != 0 {
+            bail!("whisper failed to decode, error {}", ret);
+        }
+        let language = unsafe { ffi::whisper_lang_str(ffi::whisper_full_lang_id(ctx)) };
+        if !language.is_null() {
+            let language = unsafe { CStr::from_ptr(language) };
+            self.recognized_language = Some(language.to_string_lossy().into_owned());
+        }
+
+        let eot = unsafe { ffi::whisper_token_eot(ctx) };
+        let n_segments = unsafe { ffi::whisper_full_n_segments(ctx) };
+        let mut segments = vec![];
+        for s in 0..n_segments {
+            let start =
+                unsafe { ffi::whisper_full_get_segment_t0(ctx, s) } as usize * SAMPLE_RATE / 100;
+            // Tokens are pieces of words, possibly even of a multibyte
+            // character, words start with a space.
+            let mut words: Vec<(Vec<u8>, f32, f32, f32)> = vec![];
+            for t in 0..unsafe { ffi::whisper_full_n_tokens(ctx, s) } {
+                // Special tokens, like timestamps, come after the end of text one.
+                if unsafe { ffi::whisper_full_get_token_id(ctx, s, t) } >= eot {
+                    continue;
+                }
+                let text = unsafe { CStr::from_ptr(ffi::whisper_full_get_token_text(ctx, s, t)) };
+                let p = unsafe { ffi::whisper_full_get_token_p(ctx, s, t) };
+                let (mut t0, mut t1) = (0, 0);
+                unsafe { ffi::trascri_whisper_token_times(ctx, s, t, &mut t0, &mut t1) };
+                let (t0, t1) = (
+                    self.window_start + t0 as f32 / 100.0,
+                    self.window_start + t1 as f32 / 100.0,
+                );
+
+                let bytes = text.to_bytes();
+                match words.last_mut() {
+                    Some(w) if !bytes.starts_with(b" ") => {
+                        w.0.extend_from_slice(bytes);
+                        w.1 = w.1.min(p);
+                        w.3 = t1;
+                    }
+                    _ => words.push((
+                        bytes.strip_prefix(b" ").unwrap_or(bytes).to_vec(),
+                        p,
+                        t0,
+                        t1,
+                    )),
+                }
+            }
+            let words = words
+                .into_iter()
+                .map(|(text, confidence, start, end)| OwnedWord {
+                    text: String::from_utf8_lossy(&text).into_owned(),
+                    confidence,
+                    start,
+                    end,
+                })
+                .filter(|w| !w.text.is_empty())
+                .collect();
+            segments.push(DecodedSegment { start, words });
+        }
+        Ok(segments)
+    }
+    // Moves the words to the result, dropping the audio before `keep_from`.
+    fn finalize(&mut self, segments: Vec<DecodedSegment>, keep_from: usize) {
+        self.result = segments.into_iter().flat_map(|s| s.words).collect();
+        self.partial.clear();
+        self.drop_audio(keep_from);
+    }
+    fn drop_audio(&mut self, until: usize) {
+        let until = until.min(self.window.len());
+        self.window.drain(..until);
+        self.window_start += until as f32 / SAMPLE_RATE as f32;
+        self.undecoded = self.window.len();
+    }
+}
+
+impl Drop for Whisper {
+    fn drop(&mut self) {
+        unsafe { ffi::whisper_free(self.ctx.as_ptr()) };
+    }
+}
+
+impl Recognizer for Whisper {
+    type Sample = f32;
+    fn feed(&mut self, data: &[Self::Sample]) -> anyhow::Result<DecodingState> {
+        self.window.extend_from_slice(data);
+        self.undecoded += data.len();
+
+        if self.window.len() >= WINDOW {
+            let mut segments = self.decode()?;
+            // The last segment is likely cut by the end of the window, it's
+            // decoded again with the next one.
+            let keep_from = match segments.len() {
+                0 | 1 => self.window.len(),
+                _ => segments.pop().unwrap().start,
+            };
+            self.finalize(segments, keep_from);
+            return Ok(DecodingState::Finalized);
+        }
+
+        let pause =
+            self.window.len() > PAUSE && is_silent(&self.window[self.window.len() - PAUSE..]);
+        if pause {
+            if is_silent(&self.window) {
+                self.drop_audio(self.window.len());
+                self.partial.clear();
+                return Ok(DecodingState::Running);
+            }
+            let segments = self.decode()?;
+            self.finalize(segments, self.window.len());
+            return Ok(DecodingState::Finalized);
+        }
+
+        if self.undecoded >= STEP && !is_silent(&self.window) {
+            let segments = self.decode()?;
+            self.partial = segments.into_iter().flat_map(|s| s.words).collect();
+        }
+        Ok(DecodingState::Running)
+    }
+    fn partial_result(&mut self) -> anyhow::Result<Recognized<'_>> {
+        Ok(Recognized::new(
+            self.partial.iter().map(OwnedWord::as_word).collect(),
+        ))
+    }
+    fn result(&mut self) -> anyhow::Result<Recognized<'_>> {
+        Ok(Recognized::new(
+            self.result.iter().map(OwnedWord::as_word).collect(),
+        ))
+    }
+    fn final_result(&mut self) -> anyhow::Result<Recognized<'_>> {
+        let segments = if is_silent(&self.window) {
+            vec![]
+        } else {
+            self.decode()?
+        };
+        self.finalize(segments, self.window.len());
+        self.result()
+    }
+    fn sample_rate(&self) -> f32 {
+        SAMPLE_RATE as f32
+    }
+    fn language(&self) -> Option<&str> {
+        self.recognized_language.as_deref()
+    }
+    fn reset(&mut self) {
+        self.window.clear();
+        self.window_start = 0.0;
+        self.undecoded = 0;
+        self.partial.clear();
+        self.result.clear();
+    }
+}
@@ -0,0 +1,31 @@
+// Removes hesitations like "uh" and "um".
+
+use crate::ports::{normalize_word, trailing_punctuation, SegmentWord, TextFilter};
+
+// The same in most languages.
+const FILLERS: &[&str] = &[
+    "uh", "uhh", "uhm", "um", "umm", "er", "erm", "ehm", "hmm", "mm", "mhm",
+];
+
+#[derive(Debug, Default)]
+pub struct Fillers;
+
+impl TextFilter for Fillers {
+    fn filter(&self, words: &mut Vec<SegmentWord>) {
+        let mut kept: Vec<SegmentWord> = Vec::with_capacity(words.len());
+        for word in words.drain(..) {
+            if !FILLERS.contains(&normalize_word(&word.text).as_str()) {
+                kept.push(word);
+                continue;
+            }
+            // The punctuation after the filler, like the end of a sentence,
+            // moves to the word before.
+            if let Some(previous) = kept.last_mut() {
+                if trailing_punctuation(&previous.text).is_empty() {
+                    previous.text.push_str(trailing_punctuation(&word.text));
+                }
+            }
+        }
+        *words = kept;
+    }
+}
@@ -0,0 +1,3 @@
+pub mod fillers;
+pub mod profanity;
+pub mod replacements;
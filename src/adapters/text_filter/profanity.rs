@@ -0,0 +1,70 @@
+// Masks swear words but their first letter, like "f***".
+
+use crate::ports::{normalize_word, SegmentWord, TextFilter};
+
+// English and Italian, with their common inflections.
+const WORDS: &[&str] = &[
+    "fuck",
+    "fucks",
+    "fucked",
+    "fucking",
+    "fucker",
+    "motherfucker",
+    "shit",
+    "shitty",
+    "bullshit",
+    "bitch",
+    "bitches",
+    "asshole",
+    "assholes",
+    "bastard",
+    "bastards",
+    "cunt",
+    "dick",
+    "dickhead",
+    "piss",
+    "pissed",
+    "cazzo",
+    "cazzi",
+    "cazzata",
+    "cazzate",
+    "merda",
+    "stronzo",
+    "stronza",
+    "stronzi",
+    "vaffanculo",
+    "fanculo",
+    "coglione",
+    "coglioni",
+    "puttana",
+    "troia",
+];
+
+fn mask(word: &str) -> String {
+    let mut seen_letter = false;
+    word.chars()
+        .map(|c| {
+            if !c.is_alphanumeric() {
+                return c;
+            }
+            if !seen_letter {
+                seen_letter = true;
+                return c;
+            }
+            '*'
+        })
+        .collect()
+}
+
+#[derive(Debug, Default)]
+pub struct Profanity;
+
+impl TextFilter for Profanity {
+    fn filter(&self, words: &mut Vec<SegmentWord>) {
+        for word in words.iter_mut() {
+            if WORDS.contains(&normalize_word(&word.text).as_str()) {
+                word.text = mask(&word.text);
+            }
+        }
+    }
+}
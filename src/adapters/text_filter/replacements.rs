@@ -0,0 +1,108 @@
+// Replaces phrases the recognizer keeps getting wrong, like "get lab" for
+// "GitLab". Phrases are matched ignoring case and punctuation.
+
+use anyhow::bail;
+
+use crate::ports::{normalize_word, SegmentWord, TextFilter};
+
+#[derive(Debug)]
+pub struct Replacements {
+    // The phrase split into normalized words, and what it's replaced with.
+    rules: Vec<(Vec<String>, String)>,
+}
+
+impl Replacements {
+    // Pairs of a phrase as it's recognized and as it should be written.
+    pub fn new(rules: &[(String, String)]) -> Self {
+        let mut rules: Vec<(Vec<String>, String)> = rules
+            .iter()
+            .map(|(heard, written)| {
+                let heard: Vec<String> = heard
+                    .split_whitespace()
+                    .map(normalize_word)
+                    .filter(|w| !w.is_empty())
+                    .collect();
+                (heard, written.trim().to_owned())
+            })
+            .filter(|(heard, _)| !heard.is_empty())
+            .collect();
+        // The longest phrases win, "get lab ci" over "get lab".
+        rules.sort_by_key(|(heard, _)| std::cmp::Reverse(heard.len()));
+        Self { rules }
+    }
+    // One rule per line, as "heard -> written". Empty lines are skipped.
+    pub fn parse_rules(text: &str) -> anyhow::Result<Vec<(String, String)>> {
+        let mut rules = vec![];
+        for (i, line) in text.lines().enumerate() {
+            if line.trim().is_empty() {
+                continue;
+            }
+            let Some((heard, written)) = line.split_once("->") else {
+                bail!("line {}: expected \"heard -> written\"", i + 1);
+            };
+            if heard.trim().is_empty() {
+                bail!("line {}: the phrase to replace is empty", i + 1);
+            }
+            rules.push((heard.trim().to_owned(), written.trim().to_owned()));
+        }
+        Ok(rules)
+    }
+    fn rule_at(&self, normalized: &[String]) -> Option<&(Vec<String>, String)> {
+        self.rules
+            .iter()
+            .find(|(heard, _)| normalized.starts_with(heard))
+    }
+}
+
+impl TextFilter for Replacements {
+    // The words of a phrase become one, spanning their times. Replacing with
+    // nothing removes the phrase.
+    fn filter(&self, words: &mut Vec<SegmentWord>) {
+        let normalized: Vec<String> = words.iter().map(|w| normalize_word(&w.text)).collect();
+        let mut replaced = Vec::with_capacity(words.len());
+        let mut i = 0;
+        while i < words.len() {
+            let Some((heard, written)) = self.rule_at(&normalized[i..]) else {
+                replaced.push(words[i].clone());
+                i += 1;
+                continue;
+            };
+            let found = &words[i..i + heard.len()];
+            i += heard.len();
+            if written.is_empty() {
+                continue;
+            }
+            replaced.push(SegmentWord::merge(found, written));
+        }
+        *words = replaced;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ports::fixtures::{text, words};
+
+    #[test]
+    fn phrases() {
+        let rules =
+            Replacements::parse_rules("get lab -> GitLab\n\nget lab ci -> GitLab CI\num ->")
+                .unwrap();
+        let replacements = Replacements::new(&rules);
+        let mut ws = words("Push to get lab, um, then Get Lab CI runs", 0.0);
+        ws[3].confidence = 0.5;
+        replacements.filter(&mut ws);
+        assert_eq!(text(&ws), "Push to GitLab, then GitLab CI runs");
+        let gitlab = &ws[2];
+        assert_eq!(
+            (gitlab.start, gitlab.end, gitlab.confidence),
+            (2.0, 3.5, 0.5)
+        );
+    }
+
+    #[test]
+    fn invalid_rules() {
+        assert!(Replacements::parse_rules("get lab = GitLab").is_err());
+        assert!(Replacements::parse_rules(" -> GitLab").is_err());
+    }
+}
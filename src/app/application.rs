@@ -47,6 +47,9 @@ mod imp {
 
             obj.setup_gactions();
             obj.set_accels_for_action("app.quit", &["<primary>q"]);
+            obj.set_accels_for_action("win.open-file", &["<primary>o"]);
+            obj.set_accels_for_action("win.next-uncertain-word", &["F8"]);
+            obj.set_accels_for_action("win.previous-uncertain-word", &["<shift>F8"]);
         }
     }
 
@@ -56,17 +59,17 @@ mod imp {
         // tries to launch a "second instance" of the application. When they try
         // to do that, we'll just present any existing window.
         fn activate(&self) {
-            let obj = self.obj();
-            // Get the current window or create one if necessary
-            let window = if let Some(window) = obj.active_window() {
-                window
-            } else {
-                let window = TrascriWindow::new(&*obj);
-                window.upcast()
-            };
-
             // Ask the window manager/compositor to present the window
+            self.obj().main_window().present();
+        }
+        // Called instead of `activate` when files are passed on the command line
+        // or opened from another app. Only the first file is transcribed.
+        fn open(&self, files: &[gio::File], _hint: &str) {
+            let window = self.obj().main_window();
             window.present();
+            if let Some(file) = files.first() {
+                window.open_file(file.clone());
+            }
         }
     }
 
@@ -88,6 +91,14 @@ impl TrascriApplication {
             .build()
     }
 
+    // Get the current window or create one if necessary
+    fn main_window(&self) -> TrascriWindow {
+        self.windows()
+            .into_iter()
+            .find_map(|w| w.downcast::<TrascriWindow>().ok())
+            .unwrap_or_else(|| TrascriWindow::new(self))
+    }
+
     fn setup_gactions(&self) {
         let quit_action = gio::SimpleAction::new("quit", None);
         quit_action.connect_activate(clone!(@weak self as app => move |_, _| {
@@ -0,0 +1,149 @@
+// Spots the phrases of a watch list in the words recognized, as they come.
+// Words are compared lowercase and without the punctuation around them.
+
+use crate::app::transcriber::{Segment, SegmentWord};
+use crate::ports::normalize_word;
+
+// Hits of the same phrase starting closer than this, in seconds, are the same
+// words recognized again, like a partial result growing into a final one.
+const SAME_HIT_TIME: f32 = 1.0;
+// How long hits are remembered to tell repeated ones apart, in seconds.
+const REPORTED_TIME: f32 = 60.0;
+
+#[derive(Debug, Clone)]
+pub struct KeywordHit {
+    // As written in the watch list.
+    pub keyword: String,
+    // Times in seconds from the start of the stream, like the words.
+    pub start: f32,
+    pub end: f32,
+    // The text of the result the phrase was found in.
+    pub context: String,
+}
+
+pub struct Spotter {
+    // The phrases as written and split into normalized words.
+    keywords: Vec<(String, Vec<String>)>,
+    // Index of the keyword and start of the hits reported recently.
+    reported: Vec<(usize, f32)>,
+}
+
+impl Spotter {
+    pub fn new(keywords: &[String]) -> Self {
+        let keywords = keywords
+            .iter()
+            .map(|k| {
+                let words: Vec<String> = k
+                    .split_whitespace()
+                    .map(normalize_word)
+                    .filter(|w| !w.is_empty())
+                    .collect();
+                (k.trim().to_owned(), words)
+            })
+            .filter(|(_, words)| !words.is_empty())
+            .collect();
+        Self {
+            keywords,
+            reported: vec![],
+        }
+    }
+    // Marks the words of the phrases found, every time. Only the hits not
+    // reported yet are returned.
+    pub fn spot(&mut self, words: &mut [SegmentWord]) -> Vec<KeywordHit> {
+        if self.keywords.is_empty() {
+            return vec![];
+        }
+        let normalized: Vec<String> = words.iter().map(|w| normalize_word(&w.text)).collect();
+        let context = words
+            .iter()
+            .map(|w| w.text.as_str())
+            .collect::<Vec<&str>>()
+            .join(" ");
+        let mut hits = vec![];
+        for (k, (keyword, phrase)) in self.keywords.iter().enumerate() {
+            for i in 0..normalized.len() {
+                if !normalized[i..].starts_with(phrase) {
+                    continue;
+                }
+                let found = &mut words[i..i + phrase.len()];
+                for word in found.iter_mut() {
+                    word.keyword = true;
+                }
+                let (start, end) = (found[0].start, found[found.len() - 1].end);
+                let repeated = self
+                    .reported
+                    .iter()
+                    .any(|&(r, r_start)| r == k && (r_start - start).abs() < SAME_HIT_TIME);
+                if repeated {
+                    continue;
+                }
+                self.reported.push((k, start));
+                hits.push(KeywordHit {
+                    keyword: keyword.clone(),
+                    start,
+                    end,
+                    context: context.clone(),
+                });
+            }
+        }
+        if let Some(last) = hits.iter().map(|h| h.start).reduce(f32::max) {
+            self.reported
+                .retain(|&(_, start)| start > last - REPORTED_TIME);
+        }
+        hits
+    }
+    // Looks in the hypothesis of the other model too, in bilingual sessions.
+    pub fn spot_segment(&mut self, segment: &mut Segment) -> Vec<KeywordHit> {
+        let mut hits = self.spot(&mut segment.words);
+        if let Some(ref mut other) = segment.other {
+            hits.extend(self.spot(&mut other.words));
+        }
+        hits
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ports::fixtures::words;
+
+    fn marked(words: &[SegmentWord]) -> Vec<&str> {
+        words
+            .iter()
+            .filter(|w| w.keyword)
+            .map(|w| w.text.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn phrases_and_punctuation() {
+        let mut spotter = Spotter::new(&["Interest rates".to_owned(), "budget".to_owned()]);
+        let mut ws = words("The budget, and interest rates.", 0.0);
+        let hits = spotter.spot(&mut ws);
+        assert_eq!(marked(&ws), ["budget,", "interest", "rates."]);
+        let keywords: Vec<&str> = hits.iter().map(|h| h.keyword.as_str()).collect();
+        assert_eq!(keywords, ["Interest rates", "budget"]);
+        assert_eq!((hits[0].start, hits[0].end), (3.0, 4.5));
+        assert_eq!(hits[0].context, "The budget, and interest rates.");
+    }
+
+    #[test]
+    fn partial_then_final() {
+        let mut spotter = Spotter::new(&["budget".to_owned()]);
+        assert_eq!(spotter.spot(&mut words("the budget", 0.0)).len(), 1);
+        // The same words, finalized with slightly different times.
+        let mut ws = words("the budget is", 0.2);
+        assert!(spotter.spot(&mut ws).is_empty());
+        assert_eq!(marked(&ws), ["budget"]);
+        // Said again later.
+        assert_eq!(spotter.spot(&mut words("budget again", 10.0)).len(), 1);
+    }
+
+    #[test]
+    fn no_keywords() {
+        let mut spotter = Spotter::new(&[" ".to_owned()]);
+        let mut ws = words("anything at all", 0.0);
+        assert!(spotter.spot(&mut ws).is_empty());
+        assert!(marked(&ws).is_empty());
+    }
+}
@@ -1,5 +1,13 @@
 pub mod application;
+pub mod keywords;
+pub mod normalization;
+pub mod punctuation;
+pub mod subtitles;
 pub mod transcriber;
 pub mod window;
 pub use application::*;
 pub use window::*;
+
+pub const SAMPLE_RATE: i32 = 16000;
+// Competing hypotheses kept for each finalized segment, when enabled.
+pub const MAX_ALTERNATIVES: u16 = 5;
@@ -0,0 +1,724 @@
+// Inverse text normalization: turns spoken numbers, dates and units into
+// written ones, like "twenty three point five percent on march fourth" into
+// "23.5% on March 4th". The words merged into one span their times.
+
+use std::ops::Range;
+
+use crate::app::punctuation::capitalize;
+use crate::app::transcriber::{Segment, SegmentWord};
+use crate::ports::normalize_word;
+
+// Spoken words read from `words`, with the number of words taken.
+type Parsed<T> = Option<(T, usize)>;
+
+struct Rules {
+    cardinal: fn(&[String]) -> Parsed<u64>,
+    // Years as they're read, like in pairs of digits in English. Only
+    // plausible years, they're taken after dates.
+    year: fn(&[String]) -> Parsed<u64>,
+    // Words after which numbers are read as years, if they can be. Elsewhere
+    // "fifteen twenty" is two numbers.
+    year_context: &'static [&'static str],
+    // The day of a date, as it's written.
+    day: fn(&[String]) -> Parsed<String>,
+    months: [&'static str; 12],
+    // "March 4th", otherwise "4 marzo".
+    month_first: bool,
+    point: &'static str,
+    minus: &'static str,
+    thousands_separator: char,
+    decimal_separator: char,
+    // Numbers that stay in words when alone, besides the ones below ten.
+    spelled_alone: &'static [&'static str],
+    // Spoken after a number and how they're written. "%" and "°" are
+    // attached to the number.
+    units: &'static [(&'static str, &'static str)],
+    // Spoken after a number and written before it, like "$".
+    currencies: &'static [(&'static str, &'static str)],
+}
+
+fn position(list: &[&str], word: &str) -> Option<u64> {
+    list.iter().position(|w| *w == word).map(|i| i as u64)
+}
+
+const EN_UNITS: &[&str] = &[
+    "zero", "one", "two", "three", "four", "five", "six", "seven", "eight", "nine",
+];
+const EN_TEENS: &[&str] = &[
+    "ten",
+    "eleven",
+    "twelve",
+    "thirteen",
+    "fourteen",
+    "fifteen",
+    "sixteen",
+    "seventeen",
+    "eighteen",
+    "nineteen",
+];
+const EN_TENS: &[&str] = &[
+    "twenty", "thirty", "forty", "fifty", "sixty", "seventy", "eighty", "ninety",
+];
+const EN_ORDINALS: &[&str] = &[
+    "first",
+    "second",
+    "third",
+    "fourth",
+    "fifth",
+    "sixth",
+    "seventh",
+    "eighth",
+    "ninth",
+    "tenth",
+    "eleventh",
+    "twelfth",
+    "thirteenth",
+    "fourteenth",
+    "fifteenth",
+    "sixteenth",
+    "seventeenth",
+    "eighteenth",
+    "nineteenth",
+];
+
+fn en_unit(word: &str) -> Option<u64> {
+    position(EN_UNITS, word).filter(|&u| u > 0)
+}
+
+fn en_below_hundred(words: &[String]) -> Parsed<u64> {
+    let first = words.first()?;
+    if let Some(t) = position(EN_TENS, first) {
+        let tens = (t + 2) * 10;
+        return Some(match words.get(1).map(String::as_str).and_then(en_unit) {
+            Some(u) => (tens + u, 2),
+            None => (tens, 1),
+        });
+    }
+    if let Some(t) = position(EN_TEENS, first) {
+        return Some((t + 10, 1));
+    }
+    Some((en_unit(first)?, 1))
+}
+
+fn en_below_thousand(words: &[String]) -> Parsed<u64> {
+    let hundreds = words.first().map(String::as_str).and_then(en_unit);
+    let (Some(h), Some("hundred")) = (hundreds, words.get(1).map(String::as_str)) else {
+        return en_below_hundred(words);
+    };
+    // "one hundred and five".
+    let rest = if words.get(2).map(String::as_str) == Some("and") {
+        3
+    } else {
+        2
+    };
+    match en_below_hundred(words.get(rest..).unwrap_or_default()) {
+        Some((v, n)) => Some((h * 100 + v, rest + n)),
+        None => Some((h * 100, 2)),
+    }
+}
+
+fn en_scale(word: &str) -> Option<u64> {
+    match word {
+        "thousand" => Some(1_000),
+        "million" => Some(1_000_000),
+        "billion" => Some(1_000_000_000),
+        _ => None,
+    }
+}
+
+fn en_cardinal(words: &[String]) -> Parsed<u64> {
+    if words.first()? == "zero" {
+        return Some((0, 1));
+    }
+    let (mut total, mut n) = (0, 0);
+    let mut last_scale = u64::MAX;
+    while let Some((v, m)) = en_below_thousand(&words[n..]) {
+        let scale = words
+            .get(n + m)
+            .map(String::as_str)
+            .and_then(en_scale)
+            .filter(|&s| s < last_scale);
+        let Some(scale) = scale else {
+            total += v;
+            n += m;
+            break;
+        };
+        total += v * scale;
+        n += m + 1;
+        last_scale = scale;
+        // "two thousand and five".
+        if words.get(n).map(String::as_str) == Some("and")
+            && en_below_thousand(&words[n + 1..]).is_some()
+        {
+            n += 1;
+        }
+    }
+    (n > 0).then_some((total, n))
+}
+
+// "nineteen ninety nine", "twenty oh five", "nineteen hundred".
+fn en_year(words: &[String]) -> Parsed<u64> {
+    let (high, n) = en_below_hundred(words).filter(|&(v, _)| v >= 10)?;
+    let rest = &words[n..];
+    let (low, m) = match rest.first().map(String::as_str) {
+        Some("hundred") => (0, 1),
+        Some("oh") => (en_unit(rest.get(1)?)?, 2),
+        _ => en_below_hundred(rest).filter(|&(v, _)| v >= 10)?,
+    };
+    Some((high * 100 + low, n + m))
+}
+
+fn en_ordinal_suffix(n: u64) -> &'static str {
+    match (n % 100, n % 10) {
+        (11..=13, _) => "th",
+        (_, 1) => "st",
+        (_, 2) => "nd",
+        (_, 3) => "rd",
+        _ => "th",
+    }
+}
+
+// Only ordinals, "march twenty" is more likely a verb than a date.
+fn en_day(words: &[String]) -> Parsed<String> {
+    let first = words.first()?;
+    let (day, n) = match first.as_str() {
+        "twentieth" => (20, 1),
+        "thirtieth" => (30, 1),
+        _ => match position(EN_ORDINALS, first) {
+            Some(o) => (o + 1, 1),
+            None => {
+                let tens = (position(EN_TENS, first)? + 2) * 10;
+                let o = position(&EN_ORDINALS[..9], words.get(1)?)?;
+                (tens + o + 1, 2)
+            }
+        },
+    };
+    (day <= 31).then(|| (format!("{}{}", day, en_ordinal_suffix(day)), n))
+}
+
+const ENGLISH: Rules = Rules {
+    cardinal: en_cardinal,
+    year: en_year,
+    year_context: &["in", "since", "until", "till", "from", "to", "by", "year"],
+    day: en_day,
+    months: [
+        "january",
+        "february",
+        "march",
+        "april",
+        "may",
+        "june",
+        "july",
+        "august",
+        "september",
+        "october",
+        "november",
+        "december",
+    ],
+    month_first: true,
+    point: "point",
+    minus: "minus",
+    thousands_separator: ',',
+    decimal_separator: '.',
+    spelled_alone: &[],
+    units: &[
+        ("percent", "%"),
+        ("per cent", "%"),
+        ("degrees celsius", "°C"),
+        ("degrees fahrenheit", "°F"),
+        ("degrees", "°"),
+        ("degree", "°"),
+        ("kilometers per hour", "km/h"),
+        ("kilometres per hour", "km/h"),
+        ("miles per hour", "mph"),
+        ("kilometers", "km"),
+        ("kilometres", "km"),
+        ("kilometer", "km"),
+        ("kilometre", "km"),
+        ("meters", "m"),
+        ("metres", "m"),
+        ("meter", "m"),
+        ("metre", "m"),
+        ("centimeters", "cm"),
+        ("centimetres", "cm"),
+        ("millimeters", "mm"),
+        ("millimetres", "mm"),
+        ("kilograms", "kg"),
+        ("kilogram", "kg"),
+        ("kilos", "kg"),
+        ("grams", "g"),
+        ("gram", "g"),
+        ("liters", "l"),
+        ("litres", "l"),
+        ("liter", "l"),
+        ("litre", "l"),
+    ],
+    currencies: &[
+        ("dollars", "$"),
+        ("dollar", "$"),
+        ("euros", "€"),
+        ("euro", "€"),
+    ],
+};
+
+const IT_UNITS: &[&str] = &[
+    "zero", "uno", "due", "tre", "quattro", "cinque", "sei", "sette", "otto", "nove",
+];
+const IT_TEENS: &[&str] = &[
+    "dieci",
+    "undici",
+    "dodici",
+    "tredici",
+    "quattordici",
+    "quindici",
+    "sedici",
+    "diciassette",
+    "diciotto",
+    "diciannove",
+];
+const IT_TENS: &[&str] = &[
+    "venti",
+    "trenta",
+    "quaranta",
+    "cinquanta",
+    "sessanta",
+    "settanta",
+    "ottanta",
+    "novanta",
+];
+
+fn it_unit(word: &str) -> Option<u64> {
+    match word {
+        "un" | "una" => Some(1),
+        // At the end of compounds, like "ventitré".
+        "tré" => Some(3),
+        _ => position(IT_UNITS, word).filter(|&u| u > 0),
+    }
+}
+
+// Italian numbers are written as one word, like "centoventitré".
+fn it_below_hundred(word: &str) -> Option<u64> {
+    if let Some(u) = it_unit(word) {
+        return Some(u);
+    }
+    if let Some(t) = position(IT_TEENS, word) {
+        return Some(t + 10);
+    }
+    IT_TENS.iter().enumerate().find_map(|(t, tens)| {
+        let value = (t as u64 + 2) * 10;
+        if word == *tens {
+            return Some(value);
+        }
+        if let Some(u) = word.strip_prefix(tens).and_then(it_unit) {
+            return Some(value + u);
+        }
+        // The vowel is dropped before "uno" and "otto", like "ventotto".
+        match word.strip_prefix(&tens[..tens.len() - 1])? {
+            "uno" | "un" => Some(value + 1),
+            "otto" => Some(value + 8),
+            _ => None,
+        }
+    })
+}
+
+fn it_below_thousand(word: &str) -> Option<u64> {
+    if let Some(v) = it_below_hundred(word) {
+        return Some(v);
+    }
+    let (left, right) = word.split_once("cent")?;
+    let hundreds = match left {
+        "" => 1,
+        _ => it_unit(left).filter(|&u| u > 1)?,
+    };
+    let rest = match right {
+        "o" => 0,
+        // "centotto" drops the vowel too.
+        _ => right
+            .strip_prefix('o')
+            .and_then(it_below_hundred)
+            .or_else(|| it_below_hundred(right).filter(|_| right.starts_with('o')))?,
+    };
+    Some(hundreds * 100 + rest)
+}
+
+// Up to the millions, which are separate words.
+fn it_word(word: &str) -> Option<u64> {
+    if word == "zero" {
+        return Some(0);
+    }
+    if let Some((left, right)) = word.split_once("mila") {
+        let thousands = it_below_thousand(left).filter(|&v| v > 1)?;
+        let rest = match right {
+            "" => 0,
+            _ => it_below_thousand(right)?,
+        };
+        return Some(thousands * 1000 + rest);
+    }
+    if let Some(right) = word.strip_prefix("mille") {
+        return match right {
+            "" => Some(1000),
+            _ => Some(1000 + it_below_thousand(right)?),
+        };
+    }
+    it_below_thousand(word)
+}
+
+fn it_scale(word: &str) -> Option<u64> {
+    match word {
+        "milione" | "milioni" => Some(1_000_000),
+        "miliardo" | "miliardi" => Some(1_000_000_000),
+        _ => None,
+    }
+}
+
+fn it_cardinal(words: &[String]) -> Parsed<u64> {
+    let (mut total, mut n) = (0, 0);
+    let mut last_scale = u64::MAX;
+    while let Some(v) = words.get(n).and_then(|w| it_word(w)) {
+        let scale = words
+            .get(n + 1)
+            .map(String::as_str)
+            .and_then(it_scale)
+            .filter(|&s| s < last_scale);
+        let Some(scale) = scale else {
+            total += v;
+            n += 1;
+            break;
+        };
+        total += v * scale;
+        n += 2;
+        last_scale = scale;
+    }
+    (n > 0).then_some((total, n))
+}
+
+// Years are read like any other number.
+fn it_year(words: &[String]) -> Parsed<u64> {
+    it_cardinal(words).filter(|&(year, _)| (1000..3000).contains(&year))
+}
+
+fn it_day(words: &[String]) -> Parsed<String> {
+    if words.first()? == "primo" {
+        return Some(("1º".to_owned(), 1));
+    }
+    let (day, n) = it_cardinal(words).filter(|&(d, _)| (1..=31).contains(&d))?;
+    Some((day.to_string(), n))
+}
+
+const ITALIAN: Rules = Rules {
+    cardinal: it_cardinal,
+    year: it_year,
+    year_context: &[],
+    day: it_day,
+    months: [
+        "gennaio",
+        "febbraio",
+        "marzo",
+        "aprile",
+        "maggio",
+        "giugno",
+        "luglio",
+        "agosto",
+        "settembre",
+        "ottobre",
+        "novembre",
+        "dicembre",
+    ],
+    month_first: false,
+    point: "virgola",
+    minus: "meno",
+    thousands_separator: '.',
+    decimal_separator: ',',
+    // Like "mille grazie".
+    spelled_alone: &["cento", "mille"],
+    units: &[
+        ("per cento", "%"),
+        ("percento", "%"),
+        ("gradi centigradi", "°C"),
+        ("gradi celsius", "°C"),
+        ("gradi", "°"),
+        ("grado", "°"),
+        ("chilometri orari", "km/h"),
+        ("chilometri all'ora", "km/h"),
+        ("chilometri", "km"),
+        ("chilometro", "km"),
+        ("metri", "m"),
+        ("metro", "m"),
+        ("centimetri", "cm"),
+        ("millimetri", "mm"),
+        ("chilogrammi", "kg"),
+        ("chili", "kg"),
+        ("chilo", "kg"),
+        ("grammi", "g"),
+        ("litri", "l"),
+        ("litro", "l"),
+        ("euro", "€"),
+    ],
+    currencies: &[],
+};
+
+// Written forms of the spoken words, each one replacing a range of them.
+type Tokens = Vec<(Range<usize>, String)>;
+
+pub struct Normalizer {
+    rules: &'static Rules,
+}
+
+impl Normalizer {
+    // Only some languages, given as a code like "en", have rules.
+    pub fn new(language: Option<&str>) -> Option<Self> {
+        let rules = match language? {
+            "en" => &ENGLISH,
+            "it" => &ITALIAN,
+            _ => return None,
+        };
+        Some(Self { rules })
+    }
+    pub fn normalize(&self, segment: &mut Segment) {
+        let words = std::mem::take(&mut segment.words);
+        let spoken: Vec<String> = words.iter().map(|w| normalize_word(&w.text)).collect();
+        let mut i = 0;
+        while i < words.len() {
+            let year_context = i > 0 && self.rules.year_context.contains(&spoken[i - 1].as_str());
+            let found = self
+                .date(&spoken[i..])
+                .or_else(|| self.number(&spoken[i..], year_context));
+            let Some((tokens, n)) = found else {
+                segment.words.push(words[i].clone());
+                i += 1;
+                continue;
+            };
+            for (range, text) in tokens {
+                segment.words.push(SegmentWord::merge(
+                    &words[i + range.start..i + range.end],
+                    &text,
+                ));
+            }
+            i += n;
+        }
+    }
+    fn month(&self, word: Option<&String>) -> Option<&'static str> {
+        let word = word?;
+        self.rules.months.iter().copied().find(|m| m == word)
+    }
+    fn date(&self, spoken: &[String]) -> Option<(Tokens, usize)> {
+        let rules = self.rules;
+        let mut tokens: Tokens = vec![];
+        if rules.month_first {
+            if let Some(month) = self.month(spoken.first()) {
+                // "march fourth"
+                let (day, n) = (rules.day)(&spoken[1..])?;
+                tokens.push((0..1, capitalize(month)));
+                tokens.push((1..1 + n, day));
+            } else {
+                // "the fourth of march"
+                let (day, n) = (rules.day)(spoken)?;
+                if spoken.get(n).map(String::as_str) != Some("of") {
+                    return None;
+                }
+                let month = self.month(spoken.get(n + 1))?;
+                tokens.push((0..n, day));
+                tokens.push((n..n + 1, "of".to_owned()));
+                tokens.push((n + 1..n + 2, capitalize(month)));
+            }
+        } else {
+            // "quattro marzo"
+            let (day, n) = (rules.day)(spoken)?;
+            let month = self.month(spoken.get(n))?;
+            tokens.push((0..n, day));
+            tokens.push((n..n + 1, month.to_owned()));
+        }
+        let end = tokens.last().unwrap().0.end;
+        match (rules.year)(&spoken[end..]) {
+            Some((year, n)) => {
+                tokens.push((end..end + n, year.to_string()));
+                Some((tokens, end + n))
+            }
+            None => Some((tokens, end)),
+        }
+    }
+    fn number(&self, spoken: &[String], year_context: bool) -> Option<(Tokens, usize)> {
+        let rules = self.rules;
+        let negative = spoken.first()? == rules.minus;
+        let start = negative as usize;
+        let year = if year_context && !negative {
+            (rules.year)(&spoken[start..])
+        } else {
+            None
+        };
+        let (value, n) = year.or_else(|| (rules.cardinal)(&spoken[start..]))?;
+        let mut end = start + n;
+        let mut text = self.format(value);
+        let mut in_context = negative;
+        if spoken.get(end) == Some(&rules.point.to_owned()) {
+            if let Some((digits, n)) = self.fraction(&spoken[end + 1..]) {
+                text = format!("{}{}{}", text, rules.decimal_separator, digits);
+                end += 1 + n;
+                in_context = true;
+            }
+        }
+        if negative {
+            text.insert(0, '-');
+        }
+
+        if let Some((written, n)) = phrase_at(rules.currencies, &spoken[end..]) {
+            return Some((vec![(0..end + n, format!("{}{}", written, text))], end + n));
+        }
+        let mut tokens = vec![];
+        match phrase_at(rules.units, &spoken[end..]) {
+            Some((written, n)) if written.starts_with(['%', '°']) => {
+                tokens.push((0..end + n, format!("{}{}", text, written)));
+                end += n;
+            }
+            Some((written, n)) => {
+                tokens.push((0..end, text));
+                tokens.push((end..end + n, written.to_owned()));
+                end += n;
+            }
+            None => {
+                let alone = n == 1 && rules.spelled_alone.contains(&spoken[start].as_str());
+                if !in_context && (value < 10 || alone) {
+                    return None;
+                }
+                tokens.push((0..end, text));
+            }
+        }
+        Some((tokens, end))
+    }
+    // The digits after the decimal point, read one by one or in groups.
+    fn fraction(&self, spoken: &[String]) -> Parsed<String> {
+        let mut digits = String::new();
+        let mut n = 0;
+        while let Some((value, m)) = (self.rules.cardinal)(&spoken[n..]) {
+            digits.push_str(&value.to_string());
+            n += m;
+            if value >= 10 {
+                break;
+            }
+        }
+        (n > 0).then_some((digits, n))
+    }
+    // Long numbers get their thousands separated, years don't.
+    fn format(&self, value: u64) -> String {
+        let digits = value.to_string();
+        if value < 10_000 {
+            return digits;
+        }
+        let groups: Vec<&str> = digits
+            .as_bytes()
+            .rchunks(3)
+            .rev()
+            .map(|g| std::str::from_utf8(g).unwrap())
+            .collect();
+        groups.join(&self.rules.thousands_separator.to_string())
+    }
+}
+
+// The longest of the phrases starting the words.
+fn phrase_at(phrases: &[(&str, &'static str)], spoken: &[String]) -> Parsed<&'static str> {
+    phrases
+        .iter()
+        .map(|(phrase, written)| (phrase.split(' ').collect::<Vec<&str>>(), *written))
+        .filter(|(phrase, _)| {
+            phrase.len() <= spoken.len() && phrase.iter().zip(spoken).all(|(p, s)| p == s)
+        })
+        .max_by_key(|(phrase, _)| phrase.len())
+        .map(|(phrase, written)| (written, phrase.len()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ports::fixtures::words;
+
+    fn normalized(language: &str, text: &str) -> String {
+        let mut s = Segment::from(words(text, 0.0));
+        Normalizer::new(Some(language)).unwrap().normalize(&mut s);
+        s.text()
+    }
+
+    #[test]
+    fn english() {
+        assert_eq!(
+            normalized("en", "twenty three point five percent on march fourth"),
+            "23.5% on March 4th"
+        );
+        assert_eq!(
+            normalized(
+                "en",
+                "it costs five dollars and weighs two thousand and five kilograms"
+            ),
+            "it costs $5 and weighs 2005 kg"
+        );
+        assert_eq!(
+            normalized("en", "one of the twenty people in nineteen ninety nine"),
+            "one of the 20 people in 1999"
+        );
+        assert_eq!(
+            normalized(
+                "en",
+                "the twenty first of may twenty twenty, minus five degrees."
+            ),
+            "the 21st of May 2020, -5°."
+        );
+        assert_eq!(
+            normalized(
+                "en",
+                "wait a second, we march twelve thousand five hundred meters"
+            ),
+            "wait a second, we march 12,500 m"
+        );
+    }
+
+    #[test]
+    fn years() {
+        assert_eq!(
+            normalized("en", "built in fifteen twenty, pages fifteen twenty"),
+            "built in 1520, pages 15 20"
+        );
+        // Any number after a date isn't its year.
+        let spoken: Vec<String> = "march fourth two thousand people"
+            .split(' ')
+            .map(str::to_owned)
+            .collect();
+        let normalizer = Normalizer::new(Some("en")).unwrap();
+        assert_eq!(normalizer.date(&spoken).map(|(_, n)| n), Some(2));
+        assert_eq!(
+            normalized("en", "march fourth nineteen ninety nine"),
+            "March 4th 1999"
+        );
+    }
+
+    #[test]
+    fn italian() {
+        assert_eq!(
+            normalized(
+                "it",
+                "ventitré virgola cinque per cento il quattro marzo duemilaventitré"
+            ),
+            "23,5% il 4 marzo 2023"
+        );
+        assert_eq!(
+            normalized("it", "mille grazie, un chilometro e duecento metri"),
+            "mille grazie, 1 km e 200 m"
+        );
+        assert_eq!(
+            normalized("it", "il primo maggio, centottanta persone e tre milioni"),
+            "il 1º maggio, 180 persone e 3.000.000"
+        );
+    }
+
+    #[test]
+    fn word_timings() {
+        let mut s = Segment::from(words("about twenty three percent", 0.0));
+        Normalizer::new(Some("en")).unwrap().normalize(&mut s);
+        let w = &s.words[1];
+        assert_eq!((w.text.as_str(), w.start, w.end), ("23%", 1.0, 3.5));
+    }
+
+    #[test]
+    fn unknown_language() {
+        assert!(Normalizer::new(Some("fr")).is_none());
+        assert!(Normalizer::new(None).is_none());
+    }
+}
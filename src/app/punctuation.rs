@@ -0,0 +1,234 @@
+// Restores sentence punctuation and capitalization in the lowercase,
+// unpunctuated output of small models. Sentences are told apart by the pauses
+// between words, questions by their first words.
+
+use crate::app::transcriber::{Segment, SegmentWord};
+
+// A pause this long, in seconds, ends a sentence.
+const SENTENCE_PAUSE: f32 = 0.7;
+// A shorter one, still longer than the gaps between words, is a comma.
+const COMMA_PAUSE: f32 = 0.35;
+
+#[derive(Debug, Default)]
+struct Rules {
+    // Words opening a question. Only the unambiguous ones, a question mark in
+    // the wrong place is worse than a missing one.
+    question_words: &'static [&'static str],
+    // Languages inverting the subject and the auxiliary in questions, like
+    // English, have plenty of sentences opening with a question word that
+    // aren't questions, like "what i mean is". There, a question word only
+    // counts when an auxiliary follows it, and an auxiliary followed by a
+    // subject opens a question too.
+    auxiliaries: &'static [&'static str],
+    subjects: &'static [&'static str],
+    // Prepended to questions, like the Spanish "¿".
+    question_opening: &'static str,
+    // Always capitalized, like the English "I". Contractions of them too.
+    capitalized: &'static [&'static str],
+}
+
+fn rules(language: &str) -> Rules {
+    match language {
+        "en" => Rules {
+            // "which" also opens relative clauses, like "which is fine".
+            question_words: &["what", "who", "where", "when", "why", "how"],
+            auxiliaries: &[
+                "is", "are", "am", "was", "were", "do", "does", "did", "can", "could", "would",
+                "should", "will", "shall", "have", "has", "had", "isn't", "aren't", "don't",
+                "doesn't", "didn't", "can't", "won't",
+            ],
+            // Not "it", as in "do it now".
+            subjects: &["i", "you", "he", "she", "we", "they", "there"],
+            capitalized: &["i"],
+            ..Default::default()
+        },
+        "it" => Rules {
+            question_words: &[
+                "chi", "cosa", "dove", "perché", "quale", "quali", "quanto", "quanta", "quanti",
+                "quante",
+            ],
+            ..Default::default()
+        },
+        "fr" => Rules {
+            question_words: &[
+                "qui", "quoi", "où", "pourquoi", "comment", "quel", "quelle", "quels", "quelles",
+                "combien", "est-ce",
+            ],
+            ..Default::default()
+        },
+        "de" => Rules {
+            question_words: &[
+                "wer", "was", "wo", "warum", "wie", "welche", "welcher", "welches", "wieso",
+                "weshalb", "woher", "wohin",
+            ],
+            ..Default::default()
+        },
+        "es" => Rules {
+            question_words: &[
+                "qué", "quién", "quiénes", "dónde", "cuál", "cuáles", "cuánto", "cuánta",
+                "cuántos", "cuántas", "cómo",
+            ],
+            question_opening: "¿",
+            ..Default::default()
+        },
+        _ => Rules::default(),
+    }
+}
+
+pub(crate) fn capitalize(s: &str) -> String {
+    let mut chars = s.chars();
+    match chars.next() {
+        Some(first) => first.to_uppercase().chain(chars).collect(),
+        None => String::new(),
+    }
+}
+
+pub struct Punctuator {
+    rules: Rules,
+}
+
+impl Punctuator {
+    // `language` is a code like "en". Other languages only get the rules
+    // based on pauses.
+    pub fn new(language: Option<&str>) -> Self {
+        Self {
+            rules: language.map(rules).unwrap_or_default(),
+        }
+    }
+    // Word timings are kept, punctuation is attached to the words.
+    pub fn punctuate(&self, segment: &mut Segment) {
+        let words = &mut segment.words;
+        // The recognizer already punctuates, like whisper does.
+        if words
+            .iter()
+            .any(|w| w.text.ends_with(['.', ',', '?', '!', ';', ':']))
+        {
+            return;
+        }
+
+        // Segments end with a pause, so they end a sentence too.
+        let mut sentence_start = 0;
+        for i in 0..words.len() {
+            let pause = words.get(i + 1).map(|next| next.start - words[i].end);
+            match pause {
+                Some(pause) if pause < SENTENCE_PAUSE => {
+                    if pause >= COMMA_PAUSE {
+                        words[i].text.push(',');
+                    }
+                }
+                _ => {
+                    self.end_sentence(&mut words[sentence_start..=i]);
+                    sentence_start = i + 1;
+                }
+            }
+        }
+        for word in words.iter_mut() {
+            let is_capitalized = self
+                .rules
+                .capitalized
+                .iter()
+                .any(|c| word.text == *c || word.text.starts_with(&format!("{}'", c)));
+            if is_capitalized {
+                word.text = capitalize(&word.text);
+            }
+        }
+    }
+    fn is_question(&self, sentence: &[SegmentWord]) -> bool {
+        let rules = &self.rules;
+        // Commas may already follow the words.
+        let word = |i: usize| sentence.get(i).map(|w| w.text.trim_end_matches(','));
+        let first = word(0).unwrap_or_default();
+        if rules.auxiliaries.is_empty() {
+            return rules.question_words.contains(&first);
+        }
+        let Some(second) = word(1) else {
+            return false;
+        };
+        if rules.question_words.contains(&first) {
+            rules.auxiliaries.contains(&second)
+        } else {
+            rules.auxiliaries.contains(&first) && rules.subjects.contains(&second)
+        }
+    }
+    fn end_sentence(&self, sentence: &mut [SegmentWord]) {
+        let is_question = self.is_question(sentence);
+        let first = &mut sentence[0];
+        first.text = capitalize(&first.text);
+        if is_question {
+            first.text.insert_str(0, self.rules.question_opening);
+        }
+        let last = &mut sentence[sentence.len() - 1];
+        last.text.push(if is_question { '?' } else { '.' });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ports::fixtures::paced_words;
+
+    fn segment(words: &[(&str, f32)]) -> Segment {
+        Segment::from(paced_words(words))
+    }
+
+    #[test]
+    fn sentences_and_commas() {
+        let mut s = segment(&[
+            ("i", 0.1),
+            ("think", 0.4),
+            ("so", 0.9),
+            ("how", 0.1),
+            ("are", 0.1),
+            ("you", 0.0),
+        ]);
+        Punctuator::new(Some("en")).punctuate(&mut s);
+        assert_eq!(s.text(), "I think, so. How are you?");
+    }
+
+    #[test]
+    fn inverted_questions() {
+        let mut s = segment(&[("can", 0.1), ("you", 0.1), ("hear", 0.1), ("me", 0.0)]);
+        Punctuator::new(Some("en")).punctuate(&mut s);
+        assert_eq!(s.text(), "Can you hear me?");
+    }
+
+    #[test]
+    fn declaratives_opening_like_questions() {
+        let mut s = segment(&[
+            ("what", 0.1),
+            ("i", 0.1),
+            ("mean", 0.1),
+            ("is", 0.1),
+            ("this", 0.9),
+            ("do", 0.1),
+            ("it", 0.1),
+            ("now", 0.9),
+            ("have", 0.1),
+            ("a", 0.1),
+            ("look", 0.0),
+        ]);
+        Punctuator::new(Some("en")).punctuate(&mut s);
+        assert_eq!(s.text(), "What I mean is this. Do it now. Have a look.");
+    }
+
+    #[test]
+    fn spanish_questions() {
+        let mut s = segment(&[("dónde", 0.1), ("estás", 0.0)]);
+        Punctuator::new(Some("es")).punctuate(&mut s);
+        assert_eq!(s.text(), "¿Dónde estás?");
+    }
+
+    #[test]
+    fn already_punctuated() {
+        let mut s = segment(&[("Hello,", 0.9), ("world.", 0.0)]);
+        Punctuator::new(Some("en")).punctuate(&mut s);
+        assert_eq!(s.text(), "Hello, world.");
+    }
+
+    #[test]
+    fn unknown_language() {
+        let mut s = segment(&[("what", 0.1), ("now", 0.0)]);
+        Punctuator::new(None).punctuate(&mut s);
+        assert_eq!(s.text(), "What now.");
+    }
+}
@@ -0,0 +1,223 @@
+// Turns finalized transcriber segments into subtitle cues and serializes them
+// as SubRip (.srt) or WebVTT (.vtt).
+
+use std::fmt::Write;
+
+use crate::app::transcriber::Segment;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Srt,
+    WebVtt,
+}
+
+impl Format {
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "srt" => Some(Self::Srt),
+            "vtt" => Some(Self::WebVtt),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct CueOptions {
+    pub max_line_len: usize,
+    pub max_lines: usize,
+    // In seconds.
+    pub max_duration: f32,
+}
+
+impl Default for CueOptions {
+    fn default() -> Self {
+        Self {
+            max_line_len: 42,
+            max_lines: 2,
+            max_duration: 5.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Cue {
+    pub start: f32,
+    pub end: f32,
+    pub lines: Vec<String>,
+}
+
+struct CueBuilder {
+    start: f32,
+    end: f32,
+    lines: Vec<String>,
+}
+
+impl CueBuilder {
+    fn new(start: f32) -> Self {
+        Self {
+            start,
+            end: start,
+            lines: vec![String::new()],
+        }
+    }
+    // Tries to append a word, returning false if it doesn't fit in this cue.
+    fn push(&mut self, text: &str, end: f32, opts: &CueOptions) -> bool {
+        let last = self.lines.last_mut().unwrap();
+        if last.is_empty() {
+            last.push_str(text);
+        } else if last.chars().count() + 1 + text.chars().count() <= opts.max_line_len {
+            last.push(' ');
+            last.push_str(text);
+        } else if self.lines.len() < opts.max_lines {
+            self.lines.push(text.to_owned());
+        } else {
+            return false;
+        }
+        self.end = end;
+        true
+    }
+    fn fits_duration(&self, end: f32, opts: &CueOptions) -> bool {
+        end - self.start <= opts.max_duration
+    }
+    fn build(self) -> Cue {
+        Cue {
+            start: self.start,
+            end: self.end,
+            lines: self.lines,
+        }
+    }
+}
+
+pub fn cues(segments: &[Segment], opts: &CueOptions) -> Vec<Cue> {
+    let mut cues = vec![];
+    for segment in segments {
+        let mut current: Option<CueBuilder> = None;
+        for w in &segment.words {
+            if let Some(ref mut c) = current {
+                if c.fits_duration(w.end, opts) && c.push(&w.text, w.end, opts) {
+                    continue;
+                }
+                cues.push(current.take().unwrap().build());
+            }
+            let mut c = CueBuilder::new(w.start);
+            c.push(&w.text, w.end, opts);
+            current = Some(c);
+        }
+        if let Some(c) = current {
+            cues.push(c.build());
+        }
+    }
+    cues
+}
+
+fn timestamp(secs: f32, decimal_sep: char) -> String {
+    let millis = (secs.max(0.0) * 1000.0).round() as u64;
+    format!(
+        "{:02}:{:02}:{:02}{}{:03}",
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        decimal_sep,
+        millis % 1000
+    )
+}
+
+pub fn write(format: Format, cues: &[Cue]) -> String {
+    let mut out = String::new();
+    if format == Format::WebVtt {
+        out.push_str("WEBVTT\n\n");
+    }
+    let sep = match format {
+        Format::Srt => ',',
+        Format::WebVtt => '.',
+    };
+    for (i, cue) in cues.iter().enumerate() {
+        if format == Format::Srt {
+            writeln!(out, "{}", i + 1).unwrap();
+        }
+        writeln!(
+            out,
+            "{} --> {}",
+            timestamp(cue.start, sep),
+            timestamp(cue.end, sep)
+        )
+        .unwrap();
+        for line in &cue.lines {
+            writeln!(out, "{}", line).unwrap();
+        }
+        out.push('\n');
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ports::fixtures::words;
+
+    #[test]
+    fn timestamps() {
+        assert_eq!(timestamp(3723.5, ','), "01:02:03,500");
+        assert_eq!(timestamp(1.0006, '.'), "00:00:01.001");
+        // Rounding carries over to the minutes.
+        assert_eq!(timestamp(59.9996, ','), "00:01:00,000");
+        assert_eq!(timestamp(-1.0, ','), "00:00:00,000");
+    }
+
+    #[test]
+    fn cue_splitting() {
+        let opts = CueOptions {
+            max_line_len: 10,
+            max_lines: 2,
+            max_duration: 5.0,
+        };
+        let segments = [
+            Segment::from(words("one two three four five six", 0.0)),
+            Segment::from(vec![]),
+            Segment::from(words("seven", 10.0)),
+        ];
+        let split: Vec<(f32, f32, Vec<String>)> = cues(&segments, &opts)
+            .into_iter()
+            .map(|c| (c.start, c.end, c.lines))
+            .collect();
+        assert_eq!(
+            split,
+            [
+                (
+                    0.0,
+                    3.5,
+                    vec!["one two".to_owned(), "three four".to_owned()]
+                ),
+                (4.0, 5.5, vec!["five six".to_owned()]),
+                (10.0, 10.5, vec!["seven".to_owned()]),
+            ]
+        );
+        // Cues never last longer than the maximum duration.
+        let opts = CueOptions {
+            max_duration: 2.0,
+            ..opts
+        };
+        let segments = [Segment::from(words("a b c", 0.0))];
+        let ends: Vec<f32> = cues(&segments, &opts).iter().map(|c| c.end).collect();
+        assert_eq!(ends, [1.5, 2.5]);
+    }
+
+    #[test]
+    fn formats() {
+        let cues = [Cue {
+            start: 1.0,
+            end: 2.25,
+            lines: vec!["hello".to_owned(), "world".to_owned()],
+        }];
+        assert_eq!(
+            write(Format::Srt, &cues),
+            "1\n00:00:01,000 --> 00:00:02,250\nhello\nworld\n\n"
+        );
+        assert_eq!(
+            write(Format::WebVtt, &cues),
+            "WEBVTT\n\n00:00:01.000 --> 00:00:02.250\nhello\nworld\n\n"
+        );
+        assert_eq!(Format::from_extension("VTT"), Some(Format::WebVtt));
+        assert_eq!(Format::from_extension("txt"), None);
+    }
+}
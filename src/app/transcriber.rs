@@ -1,60 +1,406 @@
 // The role of this module is to glue the audio_src and recognizer adapters, run
 // a gst_pipeline in a separate thread and offer a simple interface to communicate with the thread.
 
-use std::sync::{Arc, Mutex};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, Weak};
 use std::thread;
+use std::time::{Duration, Instant};
 
+use anyhow::{anyhow, Context};
 use byte_slice_cast::*;
 use gst::element_error;
 use gst::prelude::*;
 use postage::mpsc::{channel, Receiver, Sender};
 use postage::prelude::*;
+use serde::Serialize;
 
-use crate::ports::Recognizer;
+use crate::adapters::models_repo::{Engine, ModelsRepo};
+use crate::adapters::recognizer::auto::Auto;
+use crate::adapters::recognizer::vosk;
+use crate::app::keywords::{KeywordHit, Spotter};
+use crate::app::normalization::Normalizer;
+use crate::app::punctuation::Punctuator;
+pub use crate::ports::SegmentWord;
+use crate::ports::{DecodingState, Recognized, Recognizer, Sample, SampleFormat, TextFilter};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SegmentAlternative {
+    pub score: f32,
+    pub words: Vec<SegmentWord>,
+}
+
+// An owned, finalized recognizer result. Times are in seconds from the
+// start of the stream.
+#[derive(Debug, Clone, Serialize)]
+pub struct Segment {
+    pub words: Vec<SegmentWord>,
+    pub start: f32,
+    pub end: f32,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub alternatives: Vec<SegmentAlternative>,
+    // Index of the speaker in the recording, when speakers are identified.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub speaker: Option<usize>,
+    // In bilingual sessions, the index of the model that recognized the
+    // segment and the hypothesis of the other model for the same audio.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub model: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub other: Option<Box<Segment>>,
+}
+
+impl Segment {
+    pub fn text(&self) -> String {
+        self.words
+            .iter()
+            .map(|w| w.text.as_str())
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+    // The distinct phrases that the competing hypotheses recognized in place
+    // of the word at `index`, from the most likely.
+    pub fn word_alternatives(&self, index: usize) -> Vec<String> {
+        let word = &self.words[index];
+        let mut phrases: Vec<String> = vec![];
+        for alt in &self.alternatives {
+            let phrase = alt
+                .words
+                .iter()
+                .filter(|w| w.start < word.end && w.end > word.start)
+                .map(|w| w.text.as_str())
+                .collect::<Vec<&str>>()
+                .join(" ");
+            if !phrase.is_empty() && phrase != word.text && !phrases.contains(&phrase) {
+                phrases.push(phrase);
+            }
+        }
+        phrases
+    }
+}
+
+impl From<Recognized<'_>> for Segment {
+    fn from(r: Recognized<'_>) -> Self {
+        Self {
+            words: r.words.into_iter().map(SegmentWord::from).collect(),
+            start: r.start,
+            end: r.end,
+            alternatives: r
+                .alternatives
+                .into_iter()
+                .map(|a| SegmentAlternative {
+                    score: a.score,
+                    words: a.words.into_iter().map(SegmentWord::from).collect(),
+                })
+                .collect(),
+            speaker: None,
+            model: None,
+            other: None,
+        }
+    }
+}
+
+#[cfg(test)]
+impl From<Vec<SegmentWord>> for Segment {
+    fn from(words: Vec<SegmentWord>) -> Self {
+        Self {
+            start: words.first().map_or(0.0, |w| w.start),
+            end: words.last().map_or(0.0, |w| w.end),
+            words,
+            alternatives: vec![],
+            speaker: None,
+            model: None,
+            other: None,
+        }
+    }
+}
+
+// Cosine similarity above which two voiceprints are taken to be of the same speaker.
+const SAME_SPEAKER_SIMILARITY: f32 = 0.4;
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    dot / (norm(a) * norm(b)).max(f32::EPSILON)
+}
+
+// Groups the segments of a recording by speaker, as they come.
+#[derive(Default)]
+struct Speakers {
+    // The sum of the voiceprints of each speaker so far.
+    voiceprints: Vec<Vec<f32>>,
+    last: Option<usize>,
+}
+
+impl Speakers {
+    fn segment(&mut self, r: Recognized<'_>) -> Segment {
+        let speaker = match r.speaker {
+            Some(ref voiceprint) => Some(self.identify(voiceprint)),
+            // Short utterances have no voiceprint, they most likely continue
+            // the current turn.
+            None if !r.words.is_empty() => self.last,
+            None => None,
+        };
+        Segment {
+            speaker,
+            ..Segment::from(r)
+        }
+    }
+    fn identify(&mut self, voiceprint: &[f32]) -> usize {
+        let closest = self
+            .voiceprints
+            .iter()
+            .map(|v| cosine_similarity(v, voiceprint))
+            .enumerate()
+            .max_by(|a, b| a.1.total_cmp(&b.1));
+        let speaker = match closest {
+            Some((i, similarity)) if similarity >= SAME_SPEAKER_SIMILARITY => {
+                for (sum, x) in self.voiceprints[i].iter_mut().zip(voiceprint) {
+                    *sum += x;
+                }
+                i
+            }
+            _ => {
+                self.voiceprints.push(voiceprint.to_vec());
+                self.voiceprints.len() - 1
+            }
+        };
+        self.last = Some(speaker);
+        speaker
+    }
+}
+
+// How the results of one recording are processed.
+#[derive(Debug, Clone, Default)]
+pub struct SessionOptions {
+    // In the language the recognizer tells, if it has rules.
+    pub punctuation: bool,
+    // Writes numbers, dates and units of final results like "23.5%", in the
+    // languages that have rules.
+    pub normalize_numbers: bool,
+    // Phrases to watch for, each hit is reported.
+    pub keywords: Vec<String>,
+    // Applied in order to every result, before punctuation and keywords.
+    pub filters: Vec<Arc<dyn TextFilter>>,
+}
+
+// Turns the results of one recording into segments, as they come.
+struct Postprocessor {
+    speakers: Speakers,
+    filters: Vec<Arc<dyn TextFilter>>,
+    normalize_numbers: bool,
+    punctuation: bool,
+}
+
+impl Postprocessor {
+    fn new(opts: &SessionOptions) -> Self {
+        Self {
+            speakers: Speakers::default(),
+            filters: opts.filters.clone(),
+            normalize_numbers: opts.normalize_numbers,
+            punctuation: opts.punctuation,
+        }
+    }
+    fn segment(&mut self, r: Recognized<'_>, language: Option<&str>) -> Segment {
+        let mut segment = self.speakers.segment(r);
+        self.filter(&mut segment.words);
+        self.finish(&mut segment, language);
+        segment
+    }
+    // Partial results are only filtered, they're normalized and punctuated
+    // once final.
+    fn filter(&self, words: &mut Vec<SegmentWord>) {
+        for filter in &self.filters {
+            filter.filter(words);
+        }
+    }
+    // Languages can change between segments, like in bilingual sessions.
+    fn finish(&self, segment: &mut Segment, language: Option<&str>) {
+        if self.normalize_numbers {
+            if let Some(normalizer) = Normalizer::new(language) {
+                normalizer.normalize(segment);
+            }
+        }
+        if self.punctuation {
+            Punctuator::new(language).punctuate(segment);
+        }
+    }
+    // The results of every model of the session for the same audio. The one
+    // scoring higher makes the segment, in bilingual sessions the other one
+    // is attached to it.
+    fn best_segment(
+        &mut self,
+        mut results: Vec<Recognized<'_>>,
+        languages: &[Option<String>],
+    ) -> Segment {
+        let best = best_hypothesis(&results);
+        let mut segment = self.segment(results.remove(best), languages[best].as_deref());
+        if let Some(r) = results.pop() {
+            let mut other = Segment {
+                speaker: segment.speaker,
+                model: Some(1 - best),
+                ..Segment::from(r)
+            };
+            self.filter(&mut other.words);
+            self.finish(&mut other, languages[1 - best].as_deref());
+            segment.model = Some(best);
+            segment.other = Some(Box::new(other));
+        }
+        segment
+    }
+}
+
+// Mean confidence of the words, hypotheses without words score lowest.
+fn score(r: &Recognized<'_>) -> f32 {
+    if r.words.is_empty() {
+        return 0.0;
+    }
+    r.words.iter().map(|w| w.confidence).sum::<f32>() / r.words.len() as f32
+}
+
+// Index of the hypothesis scoring higher, the first one on ties.
+fn best_hypothesis(results: &[Recognized<'_>]) -> usize {
+    let mut best = 0;
+    for (i, r) in results.iter().enumerate() {
+        if score(r) > score(&results[best]) {
+            best = i;
+        }
+    }
+    best
+}
 
 #[derive(Debug)]
 pub enum Msg {
-    PartialResult(String),
-    Result(String),
+    PartialResult(Vec<SegmentWord>),
+    Result(Segment),
+    // Only sent for sources with a known duration, like files.
+    Progress {
+        position: gst::ClockTime,
+        duration: gst::ClockTime,
+    },
     Stopped,
     Started,
+    // A phrase of the watch list was heard. It follows the result it's in.
+    KeywordHit(KeywordHit),
+    // The model couldn't be loaded or the pipeline failed. It's followed by
+    // `Stopped`.
+    Error(anyhow::Error),
 }
 
 #[derive(Debug, Clone)]
 pub enum InMsg {
-    Start(Sender<Msg>),
+    Start(Sender<Msg>, SessionOptions),
     Stop,
     SetElement(gst::Element),
     Reset,
 }
 
+// Fails once the results aren't received anymore, which ends the recording.
+fn send(results_out: &mut Sender<Msg>, msg: Msg) -> anyhow::Result<()> {
+    results_out
+        .blocking_send(msg)
+        .map_err(|_| anyhow!("the results aren't received anymore"))
+}
+
+// Sends a result followed by the hits found in it.
+fn send_with_hits(
+    results_out: &mut Sender<Msg>,
+    msg: Msg,
+    hits: Vec<KeywordHit>,
+) -> anyhow::Result<()> {
+    send(results_out, msg)?;
+    for hit in hits {
+        send(results_out, Msg::KeywordHit(hit))?;
+    }
+    Ok(())
+}
+
+// Sends a final result. Without any word it's left out, only the partial
+// result shown, if any, is cleared.
+fn send_result(
+    results_out: &mut Sender<Msg>,
+    segment: Segment,
+    hits: Vec<KeywordHit>,
+    partial_shown: &mut bool,
+) -> anyhow::Result<()> {
+    if segment.words.is_empty() {
+        if std::mem::take(partial_shown) {
+            send(results_out, Msg::PartialResult(vec![]))?;
+        }
+        return Ok(());
+    }
+    *partial_shown = false;
+    send_with_hits(results_out, Msg::Result(segment), hits)
+}
+
+// Taken before the results, which borrow the recognizers.
+fn languages<S: Sample>(recognizers: &Recognizers<S>) -> Vec<Option<String>> {
+    recognizers
+        .iter()
+        .map(|r| r.language().map(str::to_owned))
+        .collect()
+}
+
+// The results of the recognizers once one of them finalized. The other ones
+// are cut at the same point, so that every hypothesis covers the same audio.
+fn finalized_results<'a, S: Sample>(
+    recognizers: &'a mut Recognizers<S>,
+    states: &[DecodingState],
+) -> anyhow::Result<Vec<Recognized<'a>>> {
+    let mut results = vec![];
+    for (recognizer, state) in recognizers.iter_mut().zip(states) {
+        results.push(match state {
+            DecodingState::Finalized => recognizer.result()?,
+            DecodingState::Running => recognizer.final_result()?,
+        });
+    }
+    Ok(results)
+}
+
+// Samples are fed to the recognizer in chunks of at least this size.
+const CHUNK_SIZE: usize = 1024 * 2;
+
+// The appsink caps are the format the recognizer wants, the source converts to them.
 fn build_pipeline(
     sample_rate: i32,
+    format: SampleFormat,
     src: &gst::Element,
 ) -> anyhow::Result<(gst::Pipeline, gst_app::AppSink)> {
     let pipeline = gst::Pipeline::new(None);
+    let format = match format {
+        SampleFormat::I16 => gst_audio::AUDIO_FORMAT_S16,
+        SampleFormat::F32 => gst_audio::AUDIO_FORMAT_F32,
+    };
 
+    // Not syncing to the clock lets files be transcribed as fast as possible.
+    // Live sources are still paced by the device.
     let appsink = gst_app::AppSink::builder()
+        .sync(false)
         .caps(
             &gst_audio::AudioCapsBuilder::new_interleaved()
                 .rate(sample_rate)
-                .format(gst_audio::AUDIO_FORMAT_S16)
+                .format(format)
                 .channels(1)
                 .build(),
         )
         .build();
-    pipeline.add(dbg!(src)).unwrap();
-    pipeline.add(&appsink).unwrap();
+    pipeline.add(dbg!(src))?;
+    pipeline.add(&appsink)?;
     src.link(&appsink)?;
     Ok((pipeline, appsink))
 }
 
-fn handle_samples(
+// `cb` receives `None` once the end of the stream is reached.
+fn handle_samples<S: Sample + FromByteSlice>(
     appsink: &gst_app::AppSink,
-    mut cb: impl FnMut(&[i16]) + std::marker::Send + 'static,
+    cb: impl FnMut(Option<&[S]>) + std::marker::Send + 'static,
 ) -> anyhow::Result<()> {
+    let cb = Arc::new(Mutex::new(cb));
+    let eos_cb = cb.clone();
     appsink.set_callbacks(
         gst_app::AppSinkCallbacks::builder()
+            .eos(move |_| {
+                (eos_cb.lock().unwrap())(None);
+            })
             // Add a handler to the "new-sample" signal.
             .new_sample(move |appsink| {
                 // Pull the sample in question out of the appsink's buffer.
@@ -88,17 +434,17 @@ fn handle_samples(
 
                 // We know what format the data in the memory region has, since we requested
                 // it by setting the appsink's caps. So what we do here is interpret the
-                // memory region we mapped as an array of signed 16 bit integers.
-                let samples: &[i16] = map.as_slice_of::<i16>().map_err(|_| {
+                // memory region we mapped as an array of samples of the recognizer's type.
+                let samples: &[S] = map.as_slice_of::<S>().map_err(|_| {
                     element_error!(
                         appsink,
                         gst::ResourceError::Failed,
-                        ("Failed to interprete buffer as S16 PCM")
+                        ("Failed to interprete buffer as PCM samples")
                     );
 
                     gst::FlowError::Error
                 })?;
-                cb(samples);
+                (cb.lock().unwrap())(Some(samples));
 
                 Ok(gst::FlowSuccess::Ok)
             })
@@ -108,61 +454,211 @@ fn handle_samples(
     Ok(())
 }
 
+type Recognizers<S> = Vec<Box<dyn Recognizer<Sample = S> + Send>>;
+
 pub struct TranscriberActor {
-    pub sender: Sender<InMsg>,
+    // The pipeline only holds a weak handle to the channel, so dropping the
+    // actor closes it and ends the thread.
+    pub sender: Arc<Sender<InMsg>>,
 }
 
 impl TranscriberActor {
-    pub fn new(
-        init_recognizer: impl Fn() -> Box<dyn Recognizer<Sample = i16> + Send> + Send + 'static,
+    pub fn new<S: Sample + FromByteSlice>(
+        init_recognizer: impl Fn() -> anyhow::Result<Box<dyn Recognizer<Sample = S> + Send>>
+            + Send
+            + 'static,
+        rms_out: Sender<f64>,
+    ) -> Self {
+        Self::with_recognizers(move || Ok(vec![init_recognizer()?]), rms_out)
+    }
+    // Feeds the same audio to one or two recognizers, sharing the sample
+    // rate. With two, every segment comes from the one scoring higher.
+    pub fn with_recognizers<S: Sample + FromByteSlice>(
+        init_recognizers: impl Fn() -> anyhow::Result<Recognizers<S>> + Send + 'static,
         rms_out: Sender<f64>,
     ) -> Self {
         let (sender, receiver) = channel(8);
+        let sender = Arc::new(sender);
 
+        let self_sender = Arc::downgrade(&sender);
         thread::spawn(move || {
-            let mut ts = Transcriber::new(receiver, init_recognizer, rms_out);
+            let mut ts = Transcriber::new(receiver, self_sender, init_recognizers, rms_out);
             ts.start_msg_loop();
         });
         Self { sender }
     }
-    pub fn start(&self, update_sender: Sender<Msg>) {
-        self.sender
-            .clone()
-            .blocking_send(InMsg::Start(update_sender))
+    // Uses the recognizer matching the engine of an installed model folder.
+    // `opts` only apply to vosk models.
+    pub fn for_model(
+        model_path: &Path,
+        sample_rate: f32,
+        opts: vosk::Options,
+        rms_out: Sender<f64>,
+    ) -> anyhow::Result<Self> {
+        let path = model_path.to_owned();
+        match ModelsRepo::engine_of(model_path) {
+            Engine::Vosk => Ok(Self::new(
+                move || Ok(Box::new(vosk::Vosk::new(&path, sample_rate, &opts)?)),
+                rms_out,
+            )),
+            #[cfg(feature = "whisper")]
+            Engine::Whisper => {
+                use crate::adapters::{models_repo::GGML_MODEL_FILE, recognizer::whisper};
+                Ok(Self::new(
+                    move || {
+                        let model_file = path.join(GGML_MODEL_FILE);
+                        Ok(Box::new(whisper::Whisper::new(
+                            &model_file,
+                            &Default::default(),
+                        )?))
+                    },
+                    rms_out,
+                ))
+            }
+            #[cfg(not(feature = "whisper"))]
+            Engine::Whisper => anyhow::bail!("trascri was built without whisper support"),
+        }
+    }
+    // Detects the language of every recording among vosk models, the model
+    // picked is sent to `detected_out`. `opts` apply to all the models.
+    pub fn auto(
+        model_paths: Vec<PathBuf>,
+        sample_rate: f32,
+        opts: vosk::Options,
+        rms_out: Sender<f64>,
+        detected_out: Sender<PathBuf>,
+    ) -> anyhow::Result<Self> {
+        if model_paths.is_empty() {
+            anyhow::bail!("language detection needs some vosk models installed");
+        }
+        // The models are compared on the acoustic confidence of their words,
+        // which vosk doesn't give along with alternatives.
+        let opts = vosk::Options {
+            max_alternatives: 0,
+            ..opts
+        };
+        Ok(Self::new(
+            move || {
+                let mut candidates = vec![];
+                for path in &model_paths {
+                    let recognizer: Box<dyn Recognizer<Sample = i16> + Send> =
+                        Box::new(vosk::Vosk::new(path, sample_rate, &opts)?);
+                    candidates.push((path.clone(), recognizer));
+                }
+                let mut detected_out = detected_out.clone();
+                Ok(Box::new(Auto::new(candidates, move |path| {
+                    let _ = detected_out.try_send(path.to_owned());
+                })))
+            },
+            rms_out,
+        ))
+    }
+    // Recognizes every segment with two vosk models, like for meetings
+    // switching between two languages. `opts` apply to both the models.
+    pub fn bilingual(
+        model_paths: [PathBuf; 2],
+        sample_rate: f32,
+        opts: vosk::Options,
+        rms_out: Sender<f64>,
+    ) -> anyhow::Result<Self> {
+        if model_paths
+            .iter()
+            .any(|path| ModelsRepo::engine_of(path) != Engine::Vosk)
+        {
+            anyhow::bail!("bilingual sessions need two vosk models");
+        }
+        // Segments are picked on the acoustic confidence of their words, which
+        // vosk doesn't give along with alternatives.
+        let opts = vosk::Options {
+            max_alternatives: 0,
+            ..opts
+        };
+        Ok(Self::with_recognizers(
+            move || {
+                let mut recognizers: Recognizers<i16> = vec![];
+                for path in &model_paths {
+                    recognizers.push(Box::new(vosk::Vosk::new(path, sample_rate, &opts)?));
+                }
+                Ok(recognizers)
+            },
+            rms_out,
+        ))
+    }
+    // Loads ahead of time the models `for_model` would use. It blocks for a
+    // few seconds the first time a model is used.
+    pub fn preload(model_path: &Path, opts: &vosk::Options) -> anyhow::Result<()> {
+        match ModelsRepo::engine_of(model_path) {
+            Engine::Vosk => vosk::ModelCache::global().preload(model_path, opts),
+            // Whisper contexts hold the decoding state, so they aren't shared:
+            // every recognizer loads its own.
+            #[cfg(feature = "whisper")]
+            Engine::Whisper => Ok(()),
+            #[cfg(not(feature = "whisper"))]
+            Engine::Whisper => anyhow::bail!("trascri was built without whisper support"),
+        }
+    }
+    pub fn start(&self, update_sender: Sender<Msg>, opts: SessionOptions) {
+        Sender::clone(&self.sender)
+            .blocking_send(InMsg::Start(update_sender, opts))
             .unwrap();
     }
     pub fn stop(&self) {
-        self.sender.clone().blocking_send(InMsg::Stop).unwrap();
+        Sender::clone(&self.sender)
+            .blocking_send(InMsg::Stop)
+            .unwrap();
     }
     pub fn set_element(&self, el: gst::Element) {
-        self.sender
-            .clone()
+        Sender::clone(&self.sender)
             .blocking_send(InMsg::SetElement(el))
             .unwrap();
     }
 }
 
-pub struct Transcriber {
-    element: gst::Element,
-    recognizer: Arc<Mutex<Box<dyn Recognizer<Sample = i16> + Send>>>,
+impl Drop for TranscriberActor {
+    fn drop(&mut self) {
+        // The running pipeline is stopped before the channel closes.
+        let _ = Sender::clone(&self.sender).try_send(InMsg::Stop);
+    }
+}
+
+// Asks the transcriber to stop, from the pipeline. Nothing is sent once the
+// actor is gone, the transcriber is stopping anyway.
+fn send_stop(sender: &Weak<Sender<InMsg>>) {
+    if let Some(sender) = sender.upgrade() {
+        let _ = Sender::clone(&sender).blocking_send(InMsg::Stop);
+    }
+}
+
+pub struct Transcriber<S> {
+    // The microphone until another source is set, if pulsesrc is installed.
+    element: Option<gst::Element>,
+    // Loaded once, an error is reported every time the transcriber is started.
+    recognizers: anyhow::Result<Arc<Mutex<Recognizers<S>>>>,
     pipeline: gst::Pipeline,
     receiver: Receiver<InMsg>,
+    // Handed to the running pipeline, which stops the transcriber when the
+    // stream ends or fails. It's weak so that it doesn't keep the channel open.
+    sender: Weak<Sender<InMsg>>,
     results_out: Option<Sender<Msg>>,
+    session: SessionOptions,
     rms_out: Sender<f64>,
 }
 
-impl Transcriber {
+impl<S: Sample + FromByteSlice> Transcriber<S> {
     pub fn new(
         receiver: Receiver<InMsg>,
-        init_recognizer: impl Fn() -> Box<dyn Recognizer<Sample = i16> + Send> + Send + 'static,
+        sender: Weak<Sender<InMsg>>,
+        init_recognizers: impl Fn() -> anyhow::Result<Recognizers<S>> + Send + 'static,
         rms_out: Sender<f64>,
     ) -> Self {
         Self {
-            element: gst::ElementFactory::make_with_name("pulsesrc", None).unwrap(),
+            element: gst::ElementFactory::make_with_name("pulsesrc", None).ok(),
             pipeline: gst::Pipeline::default(),
-            recognizer: Arc::new(Mutex::new(init_recognizer())),
+            recognizers: init_recognizers().map(|r| Arc::new(Mutex::new(r))),
             receiver,
+            sender,
             results_out: None,
+            session: SessionOptions::default(),
             rms_out,
         }
     }
@@ -170,28 +666,61 @@ impl Transcriber {
         dbg!(&msg);
         match msg {
             InMsg::SetElement(el) => {
-                self.element = el;
+                self.element = Some(el);
             }
-            InMsg::Start(chan) => {
+            InMsg::Start(chan, opts) => {
                 self.stop();
                 self.results_out = Some(chan.clone());
-                self.rebuild_pipeline();
-                self.start_pipeline_loop().unwrap();
+                self.session = opts;
+                if let Err(e) = self
+                    .rebuild_pipeline()
+                    .and_then(|_| self.start_pipeline_loop())
+                {
+                    self.fail(e);
+                }
             }
             InMsg::Stop => self.stop(),
             InMsg::Reset => {
-                self.recognizer.lock().unwrap().reset();
+                self.reset_recognizers();
             }
         }
     }
+    fn fail(&mut self, e: anyhow::Error) {
+        self.report(e);
+        self.stop();
+    }
+    fn report(&mut self, e: anyhow::Error) {
+        if let Some(ref mut results_out) = self.results_out {
+            let _ = results_out.blocking_send(Msg::Error(e));
+        }
+    }
     fn stop(&mut self) {
-        self.pipeline.set_state(gst::State::Null).unwrap();
-        self.pipeline.remove(&self.element).unwrap();
-        self.recognizer.lock().unwrap().reset();
+        // The pipeline is dropped anyway, elements failing to stop are only
+        // reported.
+        if let Err(e) = self.pipeline.set_state(gst::State::Null) {
+            self.report(anyhow!("couldn't stop the pipeline: {}", e));
+        }
+        if let Some(element) = self.element.clone() {
+            if element.has_as_parent(&self.pipeline) {
+                if let Err(e) = self.pipeline.remove(&element) {
+                    self.report(anyhow!("couldn't detach the audio source: {}", e));
+                }
+            }
+        }
+        // Dropping the pipeline releases the sender held by its callbacks.
+        self.pipeline = gst::Pipeline::default();
+        self.reset_recognizers();
         self.results_out
             .take()
             .map(|mut x| x.blocking_send(Msg::Stopped));
     }
+    fn reset_recognizers(&self) {
+        if let Ok(ref recognizers) = self.recognizers {
+            for recognizer in recognizers.lock().unwrap().iter_mut() {
+                recognizer.reset();
+            }
+        }
+    }
     fn start_msg_loop(&mut self) {
         dbg!("Msg loop started");
         while let Some(msg) = self.receiver.blocking_recv() {
@@ -204,90 +733,160 @@ impl Transcriber {
         if self.pipeline.state(None).1 == gst::State::Playing {
             return Err(anyhow::anyhow!("Pipeline is already running!"));
         }
-        self.pipeline.set_state(gst::State::Playing)?;
-
         let bus = self
             .pipeline
             .bus()
             .expect("Pipeline without bus. Shouldn't happen!");
 
-        let pipeline = self.pipeline.clone();
-
-        self.results_out
-            .as_mut()
-            .unwrap()
-            .blocking_send(Msg::Started)
-            .unwrap();
-        bus.connect_message(None, move |_, msg| {
-            use gst::MessageView;
-
-            match dbg!(msg.view()) {
-                MessageView::Eos(..) => {
-                    dbg!("Received Eos");
-                    pipeline.set_state(gst::State::Null).unwrap();
-                }
-                MessageView::Error(err) => {
-                    dbg!(err);
-                    pipeline.set_state(gst::State::Null).unwrap();
-                }
-                _ => (),
+        // The end of stream is handled by the appsink, errors are handled here.
+        // A sync handler is used so that no main loop is required.
+        let sender = self.sender.clone();
+        let results_out = self.results_out.clone().unwrap();
+        bus.set_sync_handler(move |_, msg| {
+            if let gst::MessageView::Error(err) = msg.view() {
+                dbg!(err);
+                let _ = results_out
+                    .clone()
+                    .blocking_send(Msg::Error(anyhow!("{}", err.error())));
+                send_stop(&sender);
             }
+            gst::BusSyncReply::Drop
         });
 
+        // Results can come as soon as the pipeline plays.
+        send(self.results_out.as_mut().unwrap(), Msg::Started)?;
+
+        // Elements post the reason of a failed state change on the bus.
+        let _ = self.pipeline.set_state(gst::State::Playing);
+
         Ok(())
     }
 
-    fn rebuild_pipeline(&mut self) {
-        let (pipeline, sink) = build_pipeline(
-            self.recognizer.lock().unwrap().sample_rate() as i32,
-            &self.element,
-        )
-        .expect("Failed to build pipeline");
+    fn rebuild_pipeline(&mut self) -> anyhow::Result<()> {
+        // The error can't be cloned, it's reported with its context as text.
+        let rec = match self.recognizers {
+            Ok(ref recognizers) => recognizers.clone(),
+            Err(ref e) => return Err(anyhow!("{:#}", e)),
+        };
+        let sample_rate = rec.lock().unwrap()[0].sample_rate() as i32;
+        let element = self
+            .element
+            .as_ref()
+            .context("no audio source, the pulsesrc GStreamer element isn't installed")?;
+        let (pipeline, sink) = build_pipeline(sample_rate, S::FORMAT, element)?;
         self.pipeline = pipeline;
 
-        const CHUNK_SIZE: usize = 1024 * 2;
+        const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
 
         let mut results_out = self.results_out.as_mut().unwrap().clone();
         let mut rms_out = self.rms_out.clone();
-        let mut buf = Vec::from([0i16; CHUNK_SIZE]);
-        let rec = self.recognizer.clone();
-        handle_samples(&sink, move |samples| {
+        let sender = self.sender.clone();
+        let mut buf: Vec<S> = Vec::with_capacity(CHUNK_SIZE);
+        let weak_sink = sink.downgrade();
+        let mut last_progress = Instant::now();
+        let mut postprocessor = Postprocessor::new(&self.session);
+        let mut spotter = Spotter::new(&self.session.keywords);
+        let mut partial_shown = false;
+        let mut process = move |samples: Option<&[S]>| -> anyhow::Result<()> {
+            let Some(samples) = samples else {
+                let mut recognizers = rec.lock().unwrap();
+                let mut states = vec![];
+                for recognizer in recognizers.iter_mut() {
+                    states.push(recognizer.feed(&buf)?);
+                }
+                buf.truncate(0);
+                // The last chunk can end an utterance, the audio after it is
+                // flushed on its own.
+                if states.contains(&DecodingState::Finalized) {
+                    let languages = languages(&recognizers);
+                    let results = finalized_results(&mut recognizers, &states)?;
+                    let mut segment = postprocessor.best_segment(results, &languages);
+                    let hits = spotter.spot_segment(&mut segment);
+                    send_result(&mut results_out, segment, hits, &mut partial_shown)?;
+                }
+                let languages = languages(&recognizers);
+                let mut results = vec![];
+                for recognizer in recognizers.iter_mut() {
+                    results.push(recognizer.final_result()?);
+                }
+                let mut segment = postprocessor.best_segment(results, &languages);
+                let hits = spotter.spot_segment(&mut segment);
+                send_result(&mut results_out, segment, hits, &mut partial_shown)?;
+                send_stop(&sender);
+                return Ok(());
+            };
             buf.extend_from_slice(samples);
             if buf.len() >= CHUNK_SIZE {
                 let sum: f64 = buf
                     .iter()
                     .map(|sample| {
-                        let f = f64::from(*sample) / f64::from(i16::MAX);
+                        let f = sample.to_f64();
                         f * f
                     })
                     .sum();
                 let rms = (sum / (samples.len() as f64)).sqrt();
-                rms_out.blocking_send(rms).unwrap();
-
-                let mut recognizer = rec.lock().unwrap();
-                let dec_state = recognizer.feed(&buf);
-                if dec_state == crate::ports::recognizer::DecodingState::Finalized {
-                    let res = recognizer.result().unwrap();
-                    let s = res
-                        .words
-                        .into_iter()
-                        .map(|w| w.text)
-                        .collect::<Vec<&str>>()
-                        .join(" ");
-                    results_out.blocking_send(Msg::Result(s)).unwrap();
+                // Transcribing goes on without anything showing the level.
+                let _ = rms_out.blocking_send(rms);
+
+                let mut recognizers = rec.lock().unwrap();
+                let mut states = vec![];
+                for recognizer in recognizers.iter_mut() {
+                    states.push(recognizer.feed(&buf)?);
+                }
+                if states.contains(&DecodingState::Finalized) {
+                    let languages = languages(&recognizers);
+                    let results = finalized_results(&mut recognizers, &states)?;
+                    let mut segment = postprocessor.best_segment(results, &languages);
+                    let hits = spotter.spot_segment(&mut segment);
+                    send_result(&mut results_out, segment, hits, &mut partial_shown)?;
                 } else {
-                    let res = recognizer.partial_result().unwrap();
-                    let s = res
-                        .words
-                        .into_iter()
-                        .map(|w| w.text)
-                        .collect::<Vec<&str>>()
-                        .join(" ");
-                    results_out.blocking_send(Msg::PartialResult(s)).unwrap();
+                    let mut results = vec![];
+                    for recognizer in recognizers.iter_mut() {
+                        results.push(recognizer.partial_result()?);
+                    }
+                    let best = results.swap_remove(best_hypothesis(&results));
+                    let mut words: Vec<SegmentWord> =
+                        best.words.into_iter().map(SegmentWord::from).collect();
+                    postprocessor.filter(&mut words);
+                    let hits = spotter.spot(&mut words);
+                    partial_shown = !words.is_empty();
+                    send_with_hits(&mut results_out, Msg::PartialResult(words), hits)?;
                 }
                 buf.truncate(0);
+                drop(recognizers);
+
+                if last_progress.elapsed() >= PROGRESS_INTERVAL {
+                    last_progress = Instant::now();
+                    let progress = weak_sink.upgrade().and_then(|sink| {
+                        Some((
+                            sink.query_position::<gst::ClockTime>()?,
+                            sink.query_duration::<gst::ClockTime>()?,
+                        ))
+                    });
+                    if let Some((position, duration)) = progress {
+                        send(&mut results_out, Msg::Progress { position, duration })?;
+                    }
+                }
+            }
+            Ok(())
+        };
+
+        let mut errors_out = self.results_out.clone().unwrap();
+        let sender = self.sender.clone();
+        let mut failed = false;
+        handle_samples(&sink, move |samples| {
+            // Samples may still arrive while the transcriber is stopping.
+            if failed {
+                return;
+            }
+            if let Err(e) = process(samples) {
+                failed = true;
+                let _ = errors_out.blocking_send(Msg::Error(e));
+                send_stop(&sender);
             }
         })
-        .unwrap();
     }
 }
+
+#[cfg(test)]
+mod tests;
@@ -0,0 +1,448 @@
+// Drives the transcriber end to end with GStreamer test sources and a
+// scripted recognizer. Needs the base and good GStreamer plugins.
+
+use std::sync::mpsc as std_mpsc;
+
+use anyhow::anyhow;
+use gtk::gio;
+
+use super::*;
+use crate::adapters::audio_src::file::File;
+use crate::adapters::recognizer::scripted::{Scripted, Step};
+use crate::adapters::text_filter::replacements::Replacements;
+use crate::ports::AudioSrc;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+// A sine wave in buffers of `CHUNK_SIZE` samples, so that every buffer is fed
+// to the recognizer on its own. Without a number of buffers, it never ends and
+// it's paced like a microphone.
+struct TestSrc {
+    buffers: Option<u32>,
+}
+
+impl AudioSrc for TestSrc {
+    fn make_element(&self) -> gst::Element {
+        let src = match self.buffers {
+            Some(n) => format!("audiotestsrc num-buffers={}", n),
+            None => "audiotestsrc is-live=true".to_owned(),
+        };
+        let description = format!(
+            "{} samplesperbuffer={} ! audioconvert ! audioresample",
+            src, CHUNK_SIZE
+        );
+        gst::parse_bin_from_description(&description, true)
+            .unwrap()
+            .upcast()
+    }
+}
+
+fn finite_src(buffers: u32) -> gst::Element {
+    TestSrc {
+        buffers: Some(buffers),
+    }
+    .make_element()
+}
+
+fn live_src() -> gst::Element {
+    TestSrc { buffers: None }.make_element()
+}
+
+fn actor_with(recognizers: impl Fn() -> Vec<Scripted> + Send + 'static) -> TranscriberActor {
+    gst::init().unwrap();
+    let (rms_s, mut rms_r) = channel::<f64>(10);
+    thread::spawn(move || while rms_r.blocking_recv().is_some() {});
+    TranscriberActor::with_recognizers(
+        move || {
+            let recognizers: Recognizers<i16> = recognizers()
+                .into_iter()
+                .map(|r| Box::new(r) as Box<dyn Recognizer<Sample = i16> + Send>)
+                .collect();
+            Ok(recognizers)
+        },
+        rms_s,
+    )
+}
+
+fn actor(script: Vec<Step>, src: gst::Element) -> TranscriberActor {
+    let actor = actor_with(move || vec![Scripted::new(script.clone(), 16000.0)]);
+    actor.set_element(src);
+    actor
+}
+
+// Progress messages are left out, their timing depends on the machine.
+fn describe(msg: Msg) -> Option<String> {
+    Some(match msg {
+        Msg::PartialResult(words) => {
+            let texts: Vec<&str> = words.iter().map(|w| w.text.as_str()).collect();
+            format!("partial: {}", texts.join(" "))
+        }
+        Msg::Result(segment) => match segment.other {
+            Some(ref other) => format!("result: {} ({})", segment.text(), other.text()),
+            None => format!("result: {}", segment.text()),
+        },
+        Msg::Progress { .. } => return None,
+        Msg::KeywordHit(hit) => format!("hit: {}", hit.keyword),
+        Msg::Started => "started".to_owned(),
+        Msg::Stopped => "stopped".to_owned(),
+        Msg::Error(e) => format!("error: {:#}", e),
+    })
+}
+
+// The messages of one start of the transcriber.
+struct Session(std_mpsc::Receiver<Msg>);
+
+impl Session {
+    fn start(actor: &TranscriberActor) -> Self {
+        Self::start_with(actor, SessionOptions::default())
+    }
+    fn start_with(actor: &TranscriberActor, opts: SessionOptions) -> Self {
+        let (s, mut r) = channel(8);
+        let (std_s, std_r) = std_mpsc::channel();
+        thread::spawn(move || {
+            while let Some(msg) = r.blocking_recv() {
+                if std_s.send(msg).is_err() {
+                    break;
+                }
+            }
+        });
+        actor.start(s, opts);
+        Self(std_r)
+    }
+    fn next(&self) -> String {
+        loop {
+            let msg = self
+                .0
+                .recv_timeout(TIMEOUT)
+                .expect("no message from the transcriber");
+            if let Some(msg) = describe(msg) {
+                return msg;
+            }
+        }
+    }
+    fn until_stopped(&self) -> Vec<String> {
+        let mut msgs = vec![];
+        loop {
+            let msg = self.next();
+            let stopped = msg == "stopped";
+            msgs.push(msg);
+            if stopped {
+                return msgs;
+            }
+        }
+    }
+    fn assert_ended(&self) {
+        let more = self.0.recv_timeout(Duration::from_millis(500));
+        assert!(more.is_err(), "message after stopped: {:?}", more);
+    }
+}
+
+fn script() -> Vec<Step> {
+    vec![
+        Step::Partial("hello"),
+        Step::Final("hello world"),
+        Step::Partial("how are"),
+    ]
+}
+
+#[test]
+fn end_of_stream() {
+    let actor = actor(script(), finite_src(4));
+    let session = Session::start(&actor);
+    assert_eq!(
+        session.until_stopped(),
+        [
+            "started",
+            "partial: hello",
+            "result: hello world",
+            "partial: how are",
+            // The script is over, the partial result stays the same.
+            "partial: how are",
+            // The pending partial result is finalized at the end of the stream.
+            "result: how are",
+            "stopped",
+        ]
+    );
+    session.assert_ended();
+}
+
+#[test]
+fn restart_after_end_of_stream() {
+    let actor = actor(script(), finite_src(2));
+    let expected = [
+        "started",
+        "partial: hello",
+        "result: hello world",
+        "stopped",
+    ];
+    assert_eq!(Session::start(&actor).until_stopped(), expected);
+    // The recognizer is reset and the source is reused.
+    assert_eq!(Session::start(&actor).until_stopped(), expected);
+}
+
+#[test]
+fn finalized_at_end_of_stream() {
+    gst::init().unwrap();
+    // Buffers shorter than `CHUNK_SIZE`: the third one is only fed at the end
+    // of the stream, and finalizes.
+    let src = gst::parse_bin_from_description(
+        "audiotestsrc num-buffers=3 samplesperbuffer=1500 ! audioconvert ! audioresample",
+        true,
+    )
+    .unwrap()
+    .upcast();
+    let script = vec![Step::Partial("hello"), Step::Final("hello world")];
+    let actor = actor(script, src);
+    let session = Session::start(&actor);
+    assert_eq!(
+        session.until_stopped(),
+        [
+            "started",
+            "partial: hello",
+            "result: hello world",
+            "stopped"
+        ]
+    );
+}
+
+#[test]
+fn empty_results() {
+    let script = vec![Step::Partial("uh"), Step::Final(""), Step::Final("")];
+    let actor = actor(script, finite_src(3));
+    let session = Session::start(&actor);
+    // Only the partial result shown is cleared.
+    assert_eq!(
+        session.until_stopped(),
+        ["started", "partial: uh", "partial: ", "stopped"]
+    );
+}
+
+#[test]
+fn stop_while_running() {
+    let actor = actor(script(), live_src());
+    let session = Session::start(&actor);
+    assert_eq!(session.next(), "started");
+    assert_eq!(session.next(), "partial: hello");
+    actor.stop();
+    let msgs = session.until_stopped();
+    let (stopped, rest) = msgs.split_last().unwrap();
+    assert_eq!(stopped, "stopped");
+    // Chunks already in flight are still recognized, but nothing is finalized
+    // on stop.
+    let script_results = ["result: hello world", "partial: how are"];
+    assert!(rest.iter().all(|m| script_results.contains(&m.as_str())));
+    session.assert_ended();
+}
+
+#[test]
+fn start_while_running() {
+    let actor = actor(script(), live_src());
+    let first = Session::start(&actor);
+    assert_eq!(first.next(), "started");
+    assert_eq!(first.next(), "partial: hello");
+
+    let second = Session::start(&actor);
+    assert_eq!(first.until_stopped().last().unwrap(), "stopped");
+    first.assert_ended();
+    // The script starts over.
+    assert_eq!(second.next(), "started");
+    assert_eq!(second.next(), "partial: hello");
+    actor.stop();
+    assert_eq!(second.until_stopped().last().unwrap(), "stopped");
+}
+
+#[test]
+fn set_element_while_running() {
+    let actor = actor(script(), live_src());
+    let session = Session::start(&actor);
+    assert_eq!(session.next(), "started");
+    assert_eq!(session.next(), "partial: hello");
+
+    // The running source keeps going, the new one is used from the next start.
+    actor.set_element(finite_src(1));
+    assert_eq!(session.next(), "result: hello world");
+    actor.stop();
+    assert_eq!(session.until_stopped().last().unwrap(), "stopped");
+
+    let session = Session::start(&actor);
+    assert_eq!(
+        session.until_stopped(),
+        ["started", "partial: hello", "result: hello", "stopped"]
+    );
+}
+
+#[test]
+fn recognizer_failure() {
+    let script = vec![Step::Partial("hello"), Step::Fail];
+    let actor = actor(script, finite_src(4));
+    let session = Session::start(&actor);
+    // The rest of the stream is ignored.
+    assert_eq!(
+        session.until_stopped(),
+        [
+            "started",
+            "partial: hello",
+            "error: scripted failure",
+            "stopped"
+        ]
+    );
+    session.assert_ended();
+}
+
+#[test]
+fn bilingual() {
+    let actor = actor_with(|| {
+        vec![
+            Scripted::new(
+                vec![
+                    Step::Partial("chow"),
+                    Step::Final("chow mondo"),
+                    Step::Partial("come"),
+                ],
+                16000.0,
+            )
+            .with_confidence(0.5),
+            Scripted::new(
+                vec![
+                    Step::Partial("ciao"),
+                    Step::Partial("ciao mondo"),
+                    Step::Partial("come stai"),
+                ],
+                16000.0,
+            )
+            .with_confidence(0.9),
+        ]
+    });
+    actor.set_element(finite_src(3));
+    let session = Session::start(&actor);
+    assert_eq!(
+        session.until_stopped(),
+        [
+            "started",
+            "partial: ciao",
+            // The second recognizer is cut where the first one finalized.
+            "result: ciao mondo (chow mondo)",
+            "partial: come stai",
+            "result: come stai (come)",
+            "stopped",
+        ]
+    );
+}
+
+#[test]
+fn keyword_hits() {
+    let actor = actor(script(), finite_src(2));
+    let opts = SessionOptions {
+        keywords: vec!["Hello".to_owned()],
+        ..Default::default()
+    };
+    let session = Session::start_with(&actor, opts);
+    assert_eq!(
+        session.until_stopped(),
+        [
+            "started",
+            "partial: hello",
+            "hit: Hello",
+            // Already reported from the partial result.
+            "result: hello world",
+            "stopped",
+        ]
+    );
+}
+
+#[test]
+fn text_filters() {
+    let actor = actor(script(), finite_src(2));
+    let rules = [("hello world".to_owned(), "Hi".to_owned())];
+    let opts = SessionOptions {
+        keywords: vec!["hi".to_owned()],
+        filters: vec![Arc::new(Replacements::new(&rules))],
+        ..Default::default()
+    };
+    let session = Session::start_with(&actor, opts);
+    // Keywords are spotted in the filtered text.
+    assert_eq!(
+        session.until_stopped(),
+        [
+            "started",
+            "partial: hello",
+            "result: Hi",
+            "hit: hi",
+            "stopped",
+        ]
+    );
+}
+
+#[test]
+fn normalized_numbers() {
+    let script = vec![
+        Step::Partial("twenty three"),
+        Step::Final("twenty three percent"),
+    ];
+    let actor =
+        actor_with(move || vec![Scripted::new(script.clone(), 16000.0).with_language("en")]);
+    actor.set_element(finite_src(2));
+    let opts = SessionOptions {
+        normalize_numbers: true,
+        ..Default::default()
+    };
+    let session = Session::start_with(&actor, opts);
+    // Only final results are normalized.
+    assert_eq!(
+        session.until_stopped(),
+        ["started", "partial: twenty three", "result: 23%", "stopped",]
+    );
+}
+
+#[test]
+fn model_failure() {
+    gst::init().unwrap();
+    let (rms_s, _) = channel::<f64>(10);
+    let actor = TranscriberActor::new::<i16>(|| Err(anyhow!("no model")), rms_s);
+    actor.set_element(finite_src(1));
+    let session = Session::start(&actor);
+    assert_eq!(session.until_stopped(), ["error: no model", "stopped"]);
+    // Every start reports it.
+    let session = Session::start(&actor);
+    assert_eq!(session.until_stopped(), ["error: no model", "stopped"]);
+}
+
+#[test]
+fn pipeline_failure() {
+    let actor = actor(script(), live_src());
+    let file = gio::File::for_path("/nonexistent/recording.wav");
+    actor.set_element(File::from(file).make_element());
+    let session = Session::start(&actor);
+    let msgs = session.until_stopped();
+    assert_eq!(msgs.first().unwrap(), "started");
+    let errors = &msgs[1..msgs.len() - 1];
+    assert!(!errors.is_empty(), "{:?}", msgs);
+    assert!(
+        errors.iter().all(|m| m.starts_with("error: ")),
+        "{:?}",
+        msgs
+    );
+}
+
+#[test]
+fn dropping_the_actor_ends_its_thread() {
+    gst::init().unwrap();
+    // The transcriber holds the only sender of the levels, they stop once its
+    // thread ends.
+    let (rms_s, mut rms_r) = channel::<f64>(10);
+    let (ended_s, ended_r) = std_mpsc::channel();
+    let levels = thread::spawn(move || {
+        while rms_r.blocking_recv().is_some() {}
+        ended_s.send(()).unwrap();
+    });
+    let actor =
+        TranscriberActor::new::<i16>(|| Ok(Box::new(Scripted::new(script(), 16000.0))), rms_s);
+    actor.set_element(live_src());
+    let session = Session::start(&actor);
+    assert_eq!(session.next(), "started");
+    drop(actor);
+    ended_r
+        .recv_timeout(TIMEOUT)
+        .expect("the transcriber thread is still running");
+    levels.join().unwrap();
+}
@@ -18,23 +18,57 @@
  * SPDX-License-Identifier: GPL-3.0-or-later
  */
 
-use std::cell::{Cell, RefCell};
-use std::path::PathBuf;
+use std::cell::{Cell, OnceCell, RefCell};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 use std::rc::Rc;
+use std::sync::Arc;
+use std::thread;
+use std::time::Instant;
 
 use adw::prelude::*;
 use adw::subclass::prelude::*;
 use glib::clone;
 use gst::prelude::DeviceExt;
-use gtk::{gdk, gio, glib, CompositeTemplate};
+use gtk::{gdk, gio, glib, pango, CompositeTemplate};
 use postage::mpsc;
-use postage::prelude::Stream;
+use postage::prelude::{Sink, Stream};
 
-use crate::adapters::models_repo::{ModelsRepo, RemoteModel};
+use crate::adapters::audio_src::file::File;
+use crate::adapters::audio_src::pulse::Pulse;
+use crate::adapters::models_repo::{
+    CancelHandle, Cancelled, Engine, ModelsRepo, RemoteModel, Variant,
+};
+use crate::adapters::recognizer::vosk;
+use crate::adapters::text_filter::{
+    fillers::Fillers, profanity::Profanity, replacements::Replacements,
+};
+use crate::app::keywords::KeywordHit;
+use crate::app::subtitles::{self, CueOptions};
 use crate::app::transcriber::*;
+use crate::app::{MAX_ALTERNATIVES, SAMPLE_RATE};
 use crate::ports::*;
 
-const SAMPLE_RATE: i32 = 16000;
+// Formats seconds as h:mm:ss or m:ss.
+fn format_time(secs: f64) -> String {
+    let secs = secs.max(0.0).round() as u64;
+    let (h, m, s) = (secs / 3600, secs / 60 % 60, secs % 60);
+    if h > 0 {
+        format!("{}:{:02}:{:02}", h, m, s)
+    } else {
+        format!("{}:{:02}", m, s)
+    }
+}
+
+// What the transcriber recognizes with.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ModelChoice {
+    Model(PathBuf),
+    // The installed vosk model fitting each recording best.
+    Auto,
+    // Two vosk models at once, each segment is taken from the one scoring higher.
+    Bilingual(PathBuf, PathBuf),
+}
 
 mod imp {
     use super::*;
@@ -46,6 +80,8 @@ mod imp {
         #[template_child]
         pub header_bar: TemplateChild<gtk::HeaderBar>,
         #[template_child]
+        pub toast_overlay: TemplateChild<adw::ToastOverlay>,
+        #[template_child]
         pub bottom_bar: TemplateChild<gtk::Box>,
         #[template_child]
         pub scrolled_win: TemplateChild<gtk::ScrolledWindow>,
@@ -53,6 +89,10 @@ mod imp {
         pub subtitle_mode_view: TemplateChild<gtk::Overlay>,
         #[template_child]
         pub text_view: TemplateChild<gtk::TextView>,
+        // The other hypothesis of each segment, next to the transcript in
+        // subtitle mode.
+        #[template_child]
+        pub other_text_view: TemplateChild<gtk::TextView>,
         #[template_child]
         pub stack: TemplateChild<gtk::Stack>,
         #[template_child]
@@ -66,17 +106,70 @@ mod imp {
         #[template_child]
         pub device_drop_down: TemplateChild<adw::ComboRow>,
         #[template_child]
+        pub file_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        pub clear_file_btn: TemplateChild<gtk::Button>,
+        #[template_child]
         pub transcriber_view: TemplateChild<gtk::Box>,
         #[template_child]
         pub model_chooser_view: TemplateChild<gtk::Box>,
         #[template_child]
         pub rms: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub progress_bar: TemplateChild<gtk::ProgressBar>,
+        #[template_child]
+        pub punctuation_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub normalize_numbers_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub identify_speakers_row: TemplateChild<adw::ActionRow>,
+        #[template_child]
+        pub identify_speakers_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub command_mode_row: TemplateChild<adw::ExpanderRow>,
+        #[template_child]
+        pub command_profile_row: TemplateChild<adw::ComboRow>,
+        #[template_child]
+        pub new_profile_row: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub command_phrases_view: TemplateChild<gtk::TextView>,
+        #[template_child]
+        pub delete_profile_btn: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub save_phrases_btn: TemplateChild<gtk::Button>,
+        #[template_child]
+        pub keywords_row: TemplateChild<adw::ExpanderRow>,
+        #[template_child]
+        pub keywords_entry: TemplateChild<adw::EntryRow>,
+        #[template_child]
+        pub fillers_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub profanity_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub replacements_view: TemplateChild<gtk::TextView>,
+        #[template_child]
+        pub replacements_error: TemplateChild<gtk::Label>,
+        #[template_child]
+        pub low_confidence_threshold: TemplateChild<gtk::SpinButton>,
+        #[template_child]
+        pub word_alternatives_switch: TemplateChild<gtk::Switch>,
+        #[template_child]
+        pub subtitle_line_length: TemplateChild<gtk::SpinButton>,
+        #[template_child]
+        pub subtitle_max_cue_duration: TemplateChild<gtk::SpinButton>,
+        pub settings: OnceCell<gio::Settings>,
         pub transcriber: RefCell<Option<TranscriberActor>>,
         pub models_repo: RefCell<Option<ModelsRepo>>,
-        pub active_model: RefCell<Option<RemoteModel>>,
+        pub active_model: RefCell<Option<ModelChoice>>,
+        // When set, it's used as input instead of the selected device.
+        pub input_file: RefCell<Option<gio::File>>,
         pub last_result_iter: RefCell<Option<gtk::TextMark>>,
+        pub segments: RefCell<Vec<Segment>>,
+        // Where each of `segments` starts in the text buffer.
+        pub segment_marks: RefCell<Vec<gtk::TextMark>>,
         pub scroll_animation: RefCell<adw::TimedAnimation>,
         pub recording: Cell<bool>,
+        pub started_at: Cell<Option<Instant>>,
     }
 
     #[glib::object_subclass]
@@ -103,6 +196,30 @@ mod imp {
                     win.set_subtitle_mode(false);
                 },
             );
+            klass.install_action("win.choose-model", None, |win, _aname, _atarget| {
+                win.open_model_chooser();
+            });
+            klass.install_action("win.next-uncertain-word", None, |win, _aname, _atarget| {
+                win.select_uncertain_word(true);
+            });
+            klass.install_action(
+                "win.previous-uncertain-word",
+                None,
+                |win, _aname, _atarget| {
+                    win.select_uncertain_word(false);
+                },
+            );
+            klass.install_action("win.export-subtitles", None, |win, _aname, _atarget| {
+                win.export_subtitles();
+            });
+            klass.install_action("win.open-file", None, |win, _aname, _atarget| {
+                let win = win.clone();
+                glib::MainContext::default().spawn_local(async move {
+                    if let Some(file) = File::get_file(Some(&win)).await {
+                        win.open_file(file);
+                    }
+                });
+            });
         }
 
         fn instance_init(obj: &glib::subclass::InitializingObject<Self>) {
@@ -134,13 +251,87 @@ impl TrascriWindow {
         };
 
         let b = gtk::Box::new(gtk::Orientation::Vertical, 16);
-        let ls = gtk::ListBox::new();
-        ls.add_css_class("boxed-list");
-        ls.set_selection_mode(gtk::SelectionMode::None);
 
-        for lang in ModelsRepo::models_iter() {
+        let usage_label = gtk::Label::builder()
+            .css_classes(vec!["dim-label".into()])
+            .build();
+        let update_usage = {
+            let models_repo = models_repo.clone();
+            Rc::new(clone!(@weak usage_label => move || {
+                match models_repo.disk_usage() {
+                    Ok(size) => usage_label.set_label(&format!(
+                        "Installed models use {}",
+                        glib::format_size(size)
+                    )),
+                    Err(e) => {
+                        dbg!(e);
+                    }
+                }
+            }))
+        };
+        update_usage();
+
+        let auto_row = adw::ActionRow::builder()
+            .title("Detect the language")
+            .subtitle("Tries every installed model on the first seconds of each recording")
+            .build();
+        let btn_auto = gtk::Button::builder()
+            .css_classes(vec!["suggested-action".to_owned()])
+            .label("Use")
+            .valign(gtk::Align::Center)
+            .build();
+        btn_auto.connect_clicked(clone!(@weak self as obj => move |_| {
+            obj.use_model(ModelChoice::Auto);
+        }));
+        auto_row.add_suffix(&btn_auto);
+        let auto_group = adw::PreferencesGroup::builder().title("Automatic").build();
+        auto_group.add(&auto_row);
+        auto_group.add(&self.bilingual_row(models_repo));
+        b.append(&auto_group);
+
+        // Whisper models get a group of their own, whatever their size.
+        let mut groups: Vec<((Engine, Option<Variant>), adw::PreferencesGroup)> = vec![];
+        let models = ModelsRepo::models_iter()
+            .filter(|m| m.engine == Engine::Vosk || cfg!(feature = "whisper"));
+        for lang in models {
+            let key = match lang.engine {
+                Engine::Vosk => (Engine::Vosk, Some(lang.variant)),
+                Engine::Whisper => (Engine::Whisper, None),
+            };
+            let group = if let Some((_, group)) = groups.iter().find(|(k, _)| *k == key) {
+                group.clone()
+            } else {
+                let (title, description) = match key.1 {
+                    Some(Variant::Small) => (
+                        "Small models",
+                        "Fast and light, good for live transcription",
+                    ),
+                    Some(Variant::Large) => (
+                        "Large models",
+                        "More accurate, but need more memory and disk space",
+                    ),
+                    None => (
+                        "Whisper models",
+                        "Far more accurate on noisy audio, but slower and with a delay",
+                    ),
+                };
+                let group = adw::PreferencesGroup::builder()
+                    .title(title)
+                    .description(description)
+                    .build();
+                b.append(&group);
+                groups.push((key, group.clone()));
+                group
+            };
+
+            let describe = {
+                let models_repo = models_repo.clone();
+                let lang = lang.clone();
+                Rc::new(move || Self::describe_model(&models_repo, &lang))
+            };
             let row = adw::ActionRow::builder()
-                .title(&lang.name)
+                .title(&glib::markup_escape_text(&lang.language))
+                .subtitle(&describe())
                 .activatable(true)
                 .build();
 
@@ -160,9 +351,23 @@ impl TrascriWindow {
                 .valign(gtk::Align::Center)
                 .build();
 
+            let progress_bar = gtk::ProgressBar::builder()
+                .valign(gtk::Align::Center)
+                .show_text(true)
+                .visible(false)
+                .build();
+
+            let btn_cancel = gtk::Button::builder()
+                .icon_name("process-stop-symbolic")
+                .valign(gtk::Align::Center)
+                .visible(false)
+                .build();
+
             row.add_suffix(&btn_remove);
             row.add_suffix(&btn_use);
             row.add_suffix(&btn_download);
+            row.add_suffix(&progress_bar);
+            row.add_suffix(&btn_cancel);
 
             let show_as_exists = Rc::new(
                 clone!(@weak btn_remove, @weak btn_use, @weak btn_download => move |exists| {
@@ -174,42 +379,164 @@ impl TrascriWindow {
 
             show_as_exists(models_repo.is_downloaded(&lang));
 
+            let show_downloading = Rc::new(
+                clone!(@weak progress_bar, @weak btn_cancel, @weak btn_download => move |downloading| {
+                    progress_bar.set_visible(downloading);
+                    btn_cancel.set_visible(downloading);
+                    btn_download.set_visible(!downloading);
+                }),
+            );
+            let cancel_handle: Rc<RefCell<Option<CancelHandle>>> = Default::default();
+
             btn_download.connect_clicked({
                 let fc = show_as_exists.clone();
                 let lang = lang.clone();
                 let models_repo = models_repo.clone();
-                move |_| {
+                let cancel_handle = cancel_handle.clone();
+                let describe = describe.clone();
+                let update_usage = update_usage.clone();
+                clone!(@weak row, @weak progress_bar => move |_| {
+                    row.set_subtitle(&describe());
+                    row.remove_css_class("error");
+                    progress_bar.set_fraction(0.0);
+                    progress_bar.set_text(None);
+                    show_downloading(true);
+
                     let fc = fc.clone();
-                    let models_repo = models_repo.clone();
-                    models_repo.download(&lang, move || {
-                        fc(true);
-                    });
+                    let show_downloading = show_downloading.clone();
+                    let describe = describe.clone();
+                    let update_usage = update_usage.clone();
+                    let handle = models_repo.download(
+                        &lang,
+                        clone!(@weak progress_bar => move |p| {
+                            match p.fraction() {
+                                Some(fraction) => progress_bar.set_fraction(fraction),
+                                None => progress_bar.pulse(),
+                            }
+                            progress_bar.set_text(Some(&format!("{:.1} MB", p.read as f64 / 1e6)));
+                        }),
+                        clone!(@weak row => move |res| {
+                            show_downloading(false);
+                            match res {
+                                Ok(()) => {
+                                    fc(true);
+                                    row.set_subtitle(&describe());
+                                    update_usage();
+                                }
+                                Err(e) => {
+                                    fc(false);
+                                    if !e.is::<Cancelled>() {
+                                        dbg!(&e);
+                                        let msg = format!("Download failed: {:#}", e);
+                                        row.set_subtitle(&glib::markup_escape_text(&msg));
+                                        row.add_css_class("error");
+                                    }
+                                }
+                            }
+                        }),
+                    );
+                    cancel_handle.replace(Some(handle));
+                })
+            });
+            btn_cancel.connect_clicked(move |_| {
+                if let Some(handle) = cancel_handle.take() {
+                    handle.cancel();
                 }
             });
             btn_remove.connect_clicked({
                 let models_repo = models_repo.clone();
                 let lang = lang.clone();
-                move |_| {
+                let update_usage = update_usage.clone();
+                clone!(@weak row => move |_| {
                     models_repo.remove(&lang).unwrap();
                     show_as_exists(false);
-                }
+                    row.set_subtitle(&describe());
+                    update_usage();
+                })
             });
             btn_use.connect_clicked({
                 let obj = self.clone();
-
+                let path = models_repo.model_path(&lang);
                 move |_| {
-                    let imp = obj.imp();
-                    imp.active_model.replace(Some(lang.clone()));
-                    imp.stack.set_visible_child(&*imp.transcriber_view);
-                    obj.setup_transcriber();
-                    obj.handle_selected_input();
+                    obj.use_model(ModelChoice::Model(path.clone()));
                 }
             });
 
-            ls.append(&row);
+            group.add(&row);
         }
 
-        b.append(&ls);
+        let imported_group = adw::PreferencesGroup::builder()
+            .title("Imported models")
+            .visible(false)
+            .build();
+        match models_repo.imported_models() {
+            Ok(names) => {
+                for name in names {
+                    imported_group.add(&self.imported_model_row(models_repo, &name));
+                    imported_group.set_visible(true);
+                }
+            }
+            Err(e) => {
+                dbg!(e);
+            }
+        }
+        b.append(&imported_group);
+
+        let import_error = gtk::Label::builder()
+            .css_classes(vec!["error".into()])
+            .wrap(true)
+            .visible(false)
+            .build();
+
+        let import_archive_btn = gtk::Button::builder()
+            .label("Import archive…")
+            .css_classes(vec!["pill".into()])
+            .build();
+        let import_folder_btn = gtk::Button::builder()
+            .label("Import folder…")
+            .css_classes(vec!["pill".into()])
+            .build();
+        for (btn, action) in [
+            (&import_archive_btn, gtk::FileChooserAction::Open),
+            (&import_folder_btn, gtk::FileChooserAction::SelectFolder),
+        ] {
+            let obj = self.clone();
+            let models_repo = models_repo.clone();
+            let update_usage = update_usage.clone();
+            btn.connect_clicked(
+                clone!(@weak imported_group, @weak import_error => move |_| {
+                    let obj = obj.clone();
+                    let models_repo = models_repo.clone();
+                    let update_usage = update_usage.clone();
+                    glib::MainContext::default().spawn_local(async move {
+                        let Some(source) = obj.choose_model_source(action).await else {
+                            return;
+                        };
+                        import_error.set_visible(false);
+                        let obj = obj.clone();
+                        models_repo.clone().import(source, move |res| match res {
+                            Ok(name) => {
+                                imported_group.add(&obj.imported_model_row(&models_repo, &name));
+                                imported_group.set_visible(true);
+                                update_usage();
+                            }
+                            Err(e) => {
+                                import_error.set_label(&format!("Import failed: {:#}", e));
+                                import_error.set_visible(true);
+                            }
+                        });
+                    });
+                }),
+            );
+        }
+        let import_box = gtk::Box::builder()
+            .spacing(8)
+            .halign(gtk::Align::Center)
+            .build();
+        import_box.append(&import_archive_btn);
+        import_box.append(&import_folder_btn);
+        b.append(&import_box);
+        b.append(&import_error);
 
         let show_folder_btn = gtk::Button::builder()
             .label("Show models folder")
@@ -225,13 +552,214 @@ impl TrascriWindow {
             );
         });
         b.append(&show_folder_btn);
+        b.append(&usage_label);
         imp.language_chooser.set_child(Some(&b));
     }
+    // Picks two of the installed vosk models, listed again every time the
+    // chooser is shown.
+    fn bilingual_row(&self, models_repo: &ModelsRepo) -> adw::ActionRow {
+        let row = adw::ActionRow::builder()
+            .title("Two languages at once")
+            .subtitle("Recognizes every segment with both models and keeps the more confident one")
+            .build();
+        let names = gtk::StringList::new(&[]);
+        let paths: Rc<RefCell<Vec<PathBuf>>> = Default::default();
+        let first = gtk::DropDown::builder()
+            .model(&names)
+            .valign(gtk::Align::Center)
+            .build();
+        let second = gtk::DropDown::builder()
+            .model(&names)
+            .valign(gtk::Align::Center)
+            .build();
+        let btn_use = gtk::Button::builder()
+            .css_classes(vec!["suggested-action".to_owned()])
+            .label("Use")
+            .valign(gtk::Align::Center)
+            .build();
+
+        let update_sensitive = clone!(@weak first, @weak second, @weak btn_use => move || {
+            let selected = (first.selected(), second.selected());
+            btn_use.set_sensitive(
+                selected.0 != gtk::INVALID_LIST_POSITION
+                    && selected.1 != gtk::INVALID_LIST_POSITION
+                    && selected.0 != selected.1,
+            );
+        });
+        first.connect_selected_notify(
+            clone!(@strong update_sensitive => move |_| update_sensitive()),
+        );
+        second.connect_selected_notify(
+            clone!(@strong update_sensitive => move |_| update_sensitive()),
+        );
+        row.connect_map({
+            let models_repo = models_repo.clone();
+            let paths = paths.clone();
+            clone!(@weak names, @weak second => move |_| {
+                let installed = models_repo.installed_models(Engine::Vosk).unwrap_or_else(|e| {
+                    dbg!(e);
+                    vec![]
+                });
+                let labels: Vec<String> = installed.iter().map(|p| Self::model_name(p)).collect();
+                let labels: Vec<&str> = labels.iter().map(String::as_str).collect();
+                names.splice(0, names.n_items(), &labels);
+                paths.replace(installed);
+                second.set_selected(1.min(names.n_items().saturating_sub(1)));
+                update_sensitive();
+            })
+        });
+        btn_use.connect_clicked(
+            clone!(@weak self as obj, @weak first, @weak second => move |_| {
+                let paths = paths.borrow();
+                let (Some(a), Some(b)) = (
+                    paths.get(first.selected() as usize),
+                    paths.get(second.selected() as usize),
+                ) else {
+                    return;
+                };
+                obj.use_model(ModelChoice::Bilingual(a.clone(), b.clone()));
+            }),
+        );
+
+        row.add_suffix(&first);
+        row.add_suffix(&second);
+        row.add_suffix(&btn_use);
+        row
+    }
+    fn describe_model(models_repo: &ModelsRepo, m: &RemoteModel) -> String {
+        let mut parts = vec![format!("{} {}", m.name, m.version).trim_end().to_owned()];
+        if let Some(ref license) = m.license {
+            parts.push(license.clone());
+        }
+        if models_repo.is_downloaded(m) {
+            if let Ok(size) = models_repo.installed_size(&m.name) {
+                parts.push(format!("{} on disk", glib::format_size(size)));
+            }
+        } else if let Some(size) = m.size {
+            parts.push(format!("{} download", glib::format_size(size)));
+        }
+        glib::markup_escape_text(&parts.join(" · ")).into()
+    }
+    // A row for a model installed from a local archive or folder.
+    fn imported_model_row(&self, models_repo: &ModelsRepo, name: &str) -> adw::ActionRow {
+        let mut subtitle = "Imported".to_owned();
+        if let Ok(size) = models_repo.installed_size(name) {
+            subtitle.push_str(&format!(" · {} on disk", glib::format_size(size)));
+        }
+        let row = adw::ActionRow::builder()
+            .title(&glib::markup_escape_text(name))
+            .subtitle(&glib::markup_escape_text(&subtitle))
+            .build();
+
+        let btn_remove = gtk::Button::builder()
+            .icon_name("user-trash-symbolic")
+            .valign(gtk::Align::Center)
+            .build();
+
+        let btn_use = gtk::Button::builder()
+            .css_classes(vec!["suggested-action".to_owned()])
+            .label("Use")
+            .valign(gtk::Align::Center)
+            .build();
+
+        row.add_suffix(&btn_remove);
+        row.add_suffix(&btn_use);
+
+        btn_remove.connect_clicked({
+            let models_repo = models_repo.clone();
+            let name = name.to_owned();
+            clone!(@weak self as obj, @weak row => move |_| {
+                if let Err(e) = models_repo.remove_local(&name) {
+                    obj.show_message(&format!("{:#}", e));
+                    return;
+                }
+                if let Some(group) = row
+                    .ancestor(adw::PreferencesGroup::static_type())
+                    .and_then(|g| g.downcast::<adw::PreferencesGroup>().ok())
+                {
+                    group.remove(&row);
+                }
+            })
+        });
+        btn_use.connect_clicked({
+            let obj = self.clone();
+            let path = models_repo.local_model_path(name);
+            move |_| {
+                obj.use_model(ModelChoice::Model(path.clone()));
+            }
+        });
+        row
+    }
+    async fn choose_model_source(&self, action: gtk::FileChooserAction) -> Option<PathBuf> {
+        let dialog =
+            gtk::FileChooserNative::new(Some("Import model"), Some(self), action, None, None);
+        if action == gtk::FileChooserAction::Open {
+            let filter = gtk::FileFilter::new();
+            filter.set_name(Some("Model archives and ggml files"));
+            filter.add_suffix("zip");
+            filter.add_suffix("bin");
+            dialog.add_filter(&filter);
+        }
+        if dialog.run_future().await != gtk::ResponseType::Accept {
+            return None;
+        }
+        dialog.file()?.path()
+    }
+    fn use_model(&self, choice: ModelChoice) {
+        let imp = self.imp();
+        let paths = self.model_paths(&choice);
+        if let Some(previous) = imp.active_model.replace(Some(choice)) {
+            for path in self.model_paths(&previous) {
+                if !paths.contains(&path) {
+                    vosk::ModelCache::global().forget(&path);
+                }
+            }
+        }
+        imp.stack.set_visible_child(&*imp.transcriber_view);
+        self.load_transcriber();
+    }
+    fn model_paths(&self, choice: &ModelChoice) -> Vec<PathBuf> {
+        match choice {
+            ModelChoice::Model(path) => vec![path.clone()],
+            ModelChoice::Bilingual(a, b) => vec![a.clone(), b.clone()],
+            ModelChoice::Auto => match *self.imp().models_repo.borrow() {
+                Some(ref repo) => repo.installed_models(Engine::Vosk).unwrap_or_else(|e| {
+                    dbg!(e);
+                    vec![]
+                }),
+                None => vec![],
+            },
+        }
+    }
+    // The language of a known model, or the name of its folder.
+    fn model_name(path: &Path) -> String {
+        let name = path
+            .file_name()
+            .map(|n| n.to_string_lossy().into_owned())
+            .unwrap_or_default();
+        ModelsRepo::models_iter()
+            .find(|m| m.name == name)
+            .map_or(name, |m| m.language)
+    }
     #[template_callback]
     fn open_model_chooser(&self) {
         let imp = self.imp();
         imp.stack.set_visible_child(&*imp.model_chooser_view);
     }
+    // Goes away on its own.
+    fn show_message(&self, text: &str) {
+        let toast = adw::Toast::new(&glib::markup_escape_text(text));
+        self.imp().toast_overlay.add_toast(&toast);
+    }
+    // Stays until dismissed, the user may want to pick another model.
+    fn show_error(&self, e: &anyhow::Error) {
+        let toast = adw::Toast::new(&glib::markup_escape_text(&format!("{:#}", e)));
+        toast.set_timeout(0);
+        toast.set_priority(adw::ToastPriority::High);
+        toast.set_button_label(Some("Change Model"));
+        toast.set_action_name(Some("win.choose-model"));
+        self.imp().toast_overlay.add_toast(&toast);
+    }
     fn handle_transcriber_msg(&self, msg: Msg) {
         let imp = self.imp();
 
@@ -267,30 +795,73 @@ impl TrascriWindow {
             }
         };
         match dbg!(msg) {
-            Msg::PartialResult(s) => {
+            Msg::PartialResult(words) => {
                 if let Some(ref mut mark) = *imp.last_result_iter.borrow_mut() {
                     b.delete(&mut b.iter_at_mark(mark), &mut b.end_iter());
                 }
 
                 let mut i = b.end_iter();
-                b.insert(&mut i, &s);
+                self.insert_words(&mut i, &words);
                 b.insert(&mut i, " ");
 
                 animate_to_bottom();
             }
-            Msg::Result(s) => {
+            Msg::Result(segment) => {
                 if let Some(ref mut mark) = *imp.last_result_iter.borrow_mut() {
                     b.delete(&mut b.iter_at_mark(mark), &mut b.end_iter());
                 }
                 let mut i = b.end_iter();
-                b.insert(&mut i, &s);
+                let previous_speaker = imp.segments.borrow().last().and_then(|s| s.speaker);
+                if let Some(speaker) = segment.speaker.filter(|s| Some(*s) != previous_speaker) {
+                    if !i.starts_line() {
+                        b.insert(&mut i, "\n");
+                    }
+                    b.insert_with_tags_by_name(
+                        &mut i,
+                        &format!("Speaker {}: ", speaker + 1),
+                        &["speaker"],
+                    );
+                }
+                let segment_mark = b.create_mark(None, &i, true);
+                self.insert_words(&mut i, &segment.words);
                 b.insert(&mut i, " ");
+                if let Some(ref other) = segment.other {
+                    let other_b = imp.other_text_view.buffer();
+                    other_b.insert(&mut other_b.end_iter(), &format!("{} ", other.text()));
+                }
 
                 animate_to_bottom();
 
                 save_mark();
+                imp.segments.borrow_mut().push(segment);
+                imp.segment_marks.borrow_mut().push(segment_mark);
+            }
+            Msg::Progress { position, duration } => {
+                let position = position.mseconds() as f64 / 1000.0;
+                let duration = duration.mseconds() as f64 / 1000.0;
+                imp.progress_bar.set_visible(true);
+                if duration > 0.0 {
+                    imp.progress_bar
+                        .set_fraction((position / duration).clamp(0.0, 1.0));
+                }
+
+                let mut text = format!("{} / {}", format_time(position), format_time(duration));
+                // Estimate the time left from the speed observed so far.
+                if let Some(started_at) = imp.started_at.get().filter(|_| position > 0.0) {
+                    let elapsed = started_at.elapsed().as_secs_f64();
+                    let eta = elapsed * (duration - position).max(0.0) / position;
+                    text.push_str(&format!(" · {} left", format_time(eta)));
+                }
+                imp.progress_bar.set_text(Some(&text));
             }
             Msg::Started => {
+                imp.started_at.set(Some(Instant::now()));
+                imp.progress_bar.set_visible(false);
+                // Segment times restart from zero with every recording.
+                imp.segments.borrow_mut().clear();
+                for mark in imp.segment_marks.take() {
+                    b.delete_mark(&mark);
+                }
                 imp.recording.replace(true);
                 imp.record_btn.remove_css_class("suggested-action");
                 imp.record_btn.add_css_class("destructive-action");
@@ -299,23 +870,321 @@ impl TrascriWindow {
             }
             Msg::Stopped => {
                 imp.recording.replace(false);
+                imp.started_at.set(None);
+                imp.progress_bar.set_visible(false);
                 imp.record_btn.remove_css_class("destructive-action");
                 imp.record_btn.add_css_class("suggested-action");
                 imp.record_btn.set_label("Start");
             }
+            Msg::KeywordHit(hit) => self.log_keyword_hit(&hit),
+            Msg::Error(e) => self.show_error(&e),
         }
     }
-    fn setup_transcriber(&self) {
+    // Adds the hit to the list and notifies it, the window may be in the
+    // background.
+    fn log_keyword_hit(&self, hit: &KeywordHit) {
+        let now = glib::DateTime::now_local()
+            .and_then(|now| now.format("%X"))
+            .map(|now| now.to_string())
+            .unwrap_or_default();
+        let row = adw::ActionRow::builder()
+            .title(&glib::markup_escape_text(&hit.keyword))
+            .subtitle(&glib::markup_escape_text(&format!(
+                "{} · {} into the recording",
+                now,
+                format_time(hit.start as f64)
+            )))
+            .tooltip_text(&hit.context)
+            .build();
+        self.imp().keywords_row.add_row(&row);
+
+        let notification = gio::Notification::new(&format!("Heard “{}”", hit.keyword));
+        notification.set_body(Some(&hit.context));
+        if let Some(app) = self.application() {
+            app.send_notification(Some("keyword-hit"), &notification);
+        }
+    }
+    fn is_uncertain(&self, word: &SegmentWord) -> bool {
+        (word.confidence as f64) < self.settings().double("low-confidence-threshold")
+    }
+    // Inserts the words separated by spaces, marking the uncertain ones.
+    fn insert_words(&self, iter: &mut gtk::TextIter, words: &[SegmentWord]) {
+        let b = self.imp().text_view.buffer();
+        for (i, word) in words.iter().enumerate() {
+            if i > 0 {
+                b.insert(iter, " ");
+            }
+            let mut tags = vec![];
+            if self.is_uncertain(word) {
+                tags.push("uncertain");
+            }
+            if word.keyword {
+                tags.push("keyword");
+            }
+            b.insert_with_tags_by_name(iter, &word.text, &tags);
+        }
+    }
+    // Marks the uncertain words of the finalized segments again, after the
+    // threshold changed.
+    fn mark_uncertain_words(&self) {
+        let imp = self.imp();
+        let b = imp.text_view.buffer();
+        b.remove_tag_by_name("uncertain", &b.start_iter(), &b.end_iter());
+        let segments = imp.segments.borrow();
+        for (segment, mark) in segments.iter().zip(imp.segment_marks.borrow().iter()) {
+            let mut start = b.iter_at_mark(mark);
+            for word in &segment.words {
+                let mut end = start;
+                end.forward_chars(word.text.chars().count() as i32);
+                // Words edited by hand are left alone.
+                if self.is_uncertain(word) && b.text(&start, &end, false) == word.text {
+                    b.apply_tag_by_name("uncertain", &start, &end);
+                }
+                start = end;
+                start.forward_char();
+            }
+        }
+    }
+    // Selects the closest uncertain word after or before the selection.
+    fn select_uncertain_word(&self, forward: bool) {
+        let text_view = &*self.imp().text_view;
+        let b = text_view.buffer();
+        let Some(tag) = b.tag_table().lookup("uncertain") else {
+            return;
+        };
+        let (mut start, end) = b.selection_bounds().unwrap_or_else(|| {
+            let cursor = b.iter_at_mark(&b.get_insert());
+            (cursor, cursor)
+        });
+        if forward {
+            start = end;
+        }
+        // Tag toggles are both the starts and the ends of the words.
+        let found = loop {
+            let moved = if forward {
+                start.forward_to_tag_toggle(Some(&tag))
+            } else {
+                start.backward_to_tag_toggle(Some(&tag))
+            };
+            if !moved || start.starts_tag(Some(&tag)) {
+                break moved;
+            }
+        };
+        if !found {
+            text_view.error_bell();
+            return;
+        }
+        let mut end = start;
+        end.forward_to_tag_toggle(Some(&tag));
+        b.select_range(&start, &end);
+        text_view.scroll_to_iter(&mut start, 0.1, false, 0.0, 0.0);
+        text_view.grab_focus();
+    }
+    // Finds the word of a finalized segment under `iter`. Returns the index of
+    // the segment, the index of the word and where the word is in the buffer.
+    fn word_at(
+        &self,
+        iter: &gtk::TextIter,
+    ) -> Option<(usize, usize, gtk::TextIter, gtk::TextIter)> {
         let imp = self.imp();
+        let b = imp.text_view.buffer();
+        let marks = imp.segment_marks.borrow();
+        let segments = imp.segments.borrow();
+        let offset = iter.offset();
 
-        let (Some(ref active_model), Some(ref models_repo)) = (
-            &*imp.active_model.borrow(),
-            &*imp.models_repo.borrow(),
-        ) else {
+        let segment_index = marks
+            .iter()
+            .rposition(|m| b.iter_at_mark(m).offset() <= offset)?;
+        let mut start = b.iter_at_mark(&marks[segment_index]);
+        for (word_index, word) in segments[segment_index].words.iter().enumerate() {
+            let mut end = start;
+            end.forward_chars(word.text.chars().count() as i32);
+            if offset < end.offset() {
+                // The text may have been edited by hand since it was inserted.
+                if b.text(&start, &end, false) != word.text {
+                    return None;
+                }
+                return Some((segment_index, word_index, start, end));
+            }
+            start = end;
+            start.forward_char();
+            if offset < start.offset() {
+                return None;
+            }
+        }
+        None
+    }
+    fn setup_word_alternatives(&self) {
+        let gesture = gtk::GestureClick::new();
+        let obj = self.clone();
+        gesture.connect_released(move |_, n_press, x, y| {
+            if n_press == 1 && !obj.imp().text_view.buffer().has_selection() {
+                obj.show_word_alternatives(x, y);
+            }
+        });
+        self.imp().text_view.add_controller(&gesture);
+    }
+    // Pops up the competing hypotheses for the word at `x`, `y`, letting the
+    // user swap one in.
+    fn show_word_alternatives(&self, x: f64, y: f64) {
+        let imp = self.imp();
+        let text_view = &*imp.text_view;
+        let b = text_view.buffer();
+
+        let (bx, by) =
+            text_view.window_to_buffer_coords(gtk::TextWindowType::Widget, x as i32, y as i32);
+        let Some(iter) = text_view.iter_at_location(bx, by) else {
+            return;
+        };
+        let Some((segment_index, word_index, start, end)) = self.word_at(&iter) else {
+            return;
+        };
+        let phrases = imp.segments.borrow()[segment_index].word_alternatives(word_index);
+        if phrases.is_empty() {
+            return;
+        }
+
+        // The buffer keeps changing while recording, so the word is tracked with marks.
+        let start_mark = b.create_mark(None, &start, true);
+        let end_mark = b.create_mark(None, &end, false);
+
+        let popover = gtk::Popover::new();
+        let list = gtk::Box::new(gtk::Orientation::Vertical, 0);
+        for phrase in phrases {
+            let btn = gtk::Button::builder()
+                .label(&phrase)
+                .css_classes(vec!["flat".into()])
+                .build();
+            btn.connect_clicked(
+                clone!(@weak self as obj, @weak popover, @weak start_mark, @weak end_mark => move |_| {
+                    obj.replace_word(segment_index, word_index, &start_mark, &end_mark, &phrase);
+                    popover.popdown();
+                }),
+            );
+            list.append(&btn);
+        }
+        popover.set_child(Some(&list));
+
+        let start_rect = text_view.iter_location(&start);
+        let end_rect = text_view.iter_location(&end);
+        let (wx, wy) = text_view.buffer_to_window_coords(
+            gtk::TextWindowType::Widget,
+            start_rect.x(),
+            start_rect.y(),
+        );
+        popover.set_pointing_to(Some(&gdk::Rectangle::new(
+            wx,
+            wy,
+            (end_rect.x() - start_rect.x()).max(1),
+            start_rect.height(),
+        )));
+        popover.set_parent(text_view);
+        popover.connect_closed(clone!(@weak b => move |popover| {
+            b.delete_mark(&start_mark);
+            b.delete_mark(&end_mark);
+            popover.unparent();
+        }));
+        popover.popup();
+    }
+    fn replace_word(
+        &self,
+        segment_index: usize,
+        word_index: usize,
+        start: &gtk::TextMark,
+        end: &gtk::TextMark,
+        phrase: &str,
+    ) {
+        let imp = self.imp();
+        let b = imp.text_view.buffer();
+        let mut start = b.iter_at_mark(start);
+        let mut end = b.iter_at_mark(end);
+
+        // The segments may belong to a newer recording by now.
+        let current = b.text(&start, &end, false);
+        if let Some(word) = imp
+            .segments
+            .borrow_mut()
+            .get_mut(segment_index)
+            .and_then(|s| s.words.get_mut(word_index))
+            .filter(|w| w.text == current)
+        {
+            word.text = phrase.to_owned();
+            word.confidence = 1.0;
+        }
+
+        b.delete(&mut start, &mut end);
+        b.insert(&mut start, phrase);
+    }
+    fn set_loading_model(&self, loading: bool) {
+        let imp = self.imp();
+        imp.record_btn.set_sensitive(!loading);
+        imp.record_btn
+            .set_label(if loading { "Loading Model…" } else { "Start" });
+    }
+    fn recognizer_options(&self) -> vosk::Options {
+        let imp = self.imp();
+        vosk::Options {
+            // In n-best mode vosk has no acoustic confidence for the words.
+            max_alternatives: if self.settings().boolean("word-alternatives") {
+                MAX_ALTERNATIVES
+            } else {
+                0
+            },
+            speaker_model_path: imp
+                .models_repo
+                .borrow()
+                .as_ref()
+                .map(|repo| repo.model_path(&ModelsRepo::speaker_model()))
+                .filter(|p| self.settings().boolean("identify-speakers") && p.is_dir()),
+            vocabulary: self.vocabulary(),
+        }
+    }
+    // Loads the model in the background, then sets up the transcriber.
+    fn load_transcriber(&self) {
+        let Some(choice) = self.imp().active_model.borrow().clone() else {
             return;
         };
+        let paths = self.model_paths(&choice);
+        let opts = self.recognizer_options();
+        self.set_loading_model(true);
+
+        let (mut s, mut r) = mpsc::channel(1);
+        thread::spawn(move || {
+            let res = paths
+                .iter()
+                .try_for_each(|path| TranscriberActor::preload(path, &opts));
+            let _ = s.blocking_send(res);
+        });
+        let obj = self.clone();
+        glib::MainContext::default().spawn_local(async move {
+            let Some(res) = r.recv().await else {
+                return;
+            };
+            // Another model may have been picked in the meantime.
+            if obj.imp().active_model.borrow().as_ref() != Some(&choice) {
+                return;
+            }
+            obj.set_loading_model(false);
+            match res {
+                Ok(()) => {
+                    obj.setup_transcriber();
+                    obj.handle_selected_input();
+                }
+                Err(e) => {
+                    obj.imp().transcriber.replace(None);
+                    obj.show_error(&e);
+                }
+            }
+        });
+    }
+    // Cheap once the model is loaded.
+    fn setup_transcriber(&self) {
+        let imp = self.imp();
 
-        let path = models_repo.model_path(active_model).clone();
+        let Some(choice) = imp.active_model.borrow().clone() else {
+            return;
+        };
+        let opts = self.recognizer_options();
 
         let (s, mut r) = mpsc::channel::<f64>(10);
         let obj = self.clone();
@@ -324,16 +1193,34 @@ impl TrascriWindow {
                 obj.imp().rms.set_label(&format!("{:.4}", msg));
             }
         });
-        imp.transcriber.replace(Some(TranscriberActor::new(
-            move || {
-                let recognizer = crate::adapters::recognizer::vosk::Vosk::new(
-                    path.as_path(),
-                    SAMPLE_RATE as f32,
-                );
-                Box::new(recognizer)
-            },
-            s,
-        )));
+        let transcriber = match choice {
+            ModelChoice::Model(ref path) => {
+                TranscriberActor::for_model(path, SAMPLE_RATE as f32, opts, s)
+            }
+            ModelChoice::Bilingual(ref a, ref b) => {
+                TranscriberActor::bilingual([a.clone(), b.clone()], SAMPLE_RATE as f32, opts, s)
+            }
+            ModelChoice::Auto => {
+                let (detected_s, mut detected_r) = mpsc::channel::<PathBuf>(1);
+                let obj = self.clone();
+                glib::MainContext::default().spawn_local(async move {
+                    while let Some(path) = detected_r.recv().await {
+                        obj.show_message(&format!("Detected {}", Self::model_name(&path)));
+                    }
+                });
+                let paths = self.model_paths(&choice);
+                TranscriberActor::auto(paths, SAMPLE_RATE as f32, opts, s, detected_s)
+            }
+        };
+        match transcriber {
+            Ok(transcriber) => {
+                imp.transcriber.replace(Some(transcriber));
+            }
+            Err(e) => {
+                imp.transcriber.replace(None);
+                self.show_error(&e);
+            }
+        }
         dbg!("tra");
     }
     fn setup_drop_down(&self) {
@@ -363,13 +1250,11 @@ impl TrascriWindow {
         });
         drop_down.set_factory(Some(&item_factory));
 
-        drop_down.set_model(Some(
-            &crate::adapters::audio_src::pulse::Pulse::list_available(),
-        ));
+        drop_down.set_model(Some(&Pulse::list_available()));
 
         let obj = self.clone();
         drop_down.connect_selected_item_notify(move |_| {
-            obj.handle_selected_input();
+            obj.set_input_file(None);
         });
         // Somehow this first selected item doesn't trigger the item_notify signal, maybe
         // it's because the selected item is already 0 by default? But checking dropdown.selected_item()
@@ -378,14 +1263,59 @@ impl TrascriWindow {
         // Manually handle first selection.
         self.handle_selected_input();
     }
+    fn setup_drop_target(&self) {
+        let drop_target = gtk::DropTarget::new(gio::File::static_type(), gdk::DragAction::COPY);
+        let obj = self.clone();
+        drop_target.connect_drop(move |_, value, _, _| {
+            let Ok(file) = value.get::<gio::File>() else {
+                return false;
+            };
+            obj.open_file(file);
+            true
+        });
+        self.add_controller(&drop_target);
+    }
+    // Transcribes `file`, starting right away if the transcriber is ready and idle.
+    pub fn open_file(&self, file: gio::File) {
+        self.set_input_file(Some(file));
+
+        let imp = self.imp();
+        if !imp.recording.get() {
+            self.start_transcriber();
+        }
+    }
+    fn set_input_file(&self, file: Option<gio::File>) {
+        let imp = self.imp();
+        match file {
+            Some(ref file) => {
+                let name = file
+                    .basename()
+                    .map(|p| p.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| file.uri().into());
+                imp.file_row.set_subtitle(&name);
+            }
+            None => imp.file_row.set_subtitle("None"),
+        }
+        imp.clear_file_btn.set_visible(file.is_some());
+        imp.input_file.replace(file);
+        self.handle_selected_input();
+    }
+    #[template_callback]
+    fn handle_clear_file_btn_clicked(&self) {
+        self.set_input_file(None);
+    }
     fn handle_selected_input(&self) {
         let imp = self.imp();
         dbg!("OK");
-        let Some(device) = imp.device_drop_down.selected_item() else {
-            return;
+        let audio_src: Box<dyn AudioSrc> = if let Some(ref file) = *imp.input_file.borrow() {
+            Box::new(File::from(file.clone()))
+        } else {
+            let Some(device) = imp.device_drop_down.selected_item() else {
+                return;
+            };
+            let device: gst::Device = device.downcast().unwrap();
+            Box::new(Pulse::from(device))
         };
-        let device: gst::Device = device.downcast().unwrap();
-        let audio_src = crate::adapters::audio_src::pulse::Pulse::from(device);
         if let Some(ref transcriber) = *imp.transcriber.borrow() {
             transcriber.set_element(audio_src.make_element());
         } else {
@@ -396,6 +1326,9 @@ impl TrascriWindow {
         let imp = self.imp();
         imp.stack.set_vhomogeneous(!active);
 
+        let bilingual = matches!(*imp.active_model.borrow(), Some(ModelChoice::Bilingual(..)));
+        imp.other_text_view.set_visible(active && bilingual);
+
         if active {
             imp.stack.set_visible_child(&*imp.subtitle_mode_view);
             imp.flap.set_content(None::<&gtk::Widget>);
@@ -433,15 +1366,415 @@ impl TrascriWindow {
         if imp.recording.get() {
             transcriber.stop();
         } else {
-            let obj = self.clone();
-            let (s, mut r) = mpsc::channel(2);
-            glib::MainContext::default().spawn_local(async move {
-                while let Some(msg) = dbg!(r.recv().await) {
-                    obj.handle_transcriber_msg(msg);
+            self.start_transcriber();
+        }
+    }
+    fn start_transcriber(&self) {
+        let imp = self.imp();
+        let Some(ref transcriber) = &*imp.transcriber.borrow() else {
+            return;
+        };
+
+        let obj = self.clone();
+        let (s, mut r) = mpsc::channel(2);
+        glib::MainContext::default().spawn_local(async move {
+            while let Some(msg) = dbg!(r.recv().await) {
+                obj.handle_transcriber_msg(msg);
+            }
+        });
+        let opts = SessionOptions {
+            punctuation: self.settings().boolean("punctuation"),
+            normalize_numbers: self.settings().boolean("normalize-numbers"),
+            keywords: self
+                .settings()
+                .strv("keywords")
+                .iter()
+                .map(|k| k.to_string())
+                .collect(),
+            filters: self.text_filters(),
+        };
+        transcriber.start(s, opts);
+    }
+    // Replacements go first, the other filters may hide what they match.
+    fn text_filters(&self) -> Vec<Arc<dyn TextFilter>> {
+        let settings = self.settings();
+        let mut filters: Vec<Arc<dyn TextFilter>> = vec![];
+        let replacements = self.replacements();
+        if !replacements.is_empty() {
+            filters.push(Arc::new(Replacements::new(&replacements)));
+        }
+        if settings.boolean("remove-filler-words") {
+            filters.push(Arc::new(Fillers));
+        }
+        if settings.boolean("mask-profanity") {
+            filters.push(Arc::new(Profanity));
+        }
+        filters
+    }
+    fn replacements(&self) -> Vec<(String, String)> {
+        self.settings()
+            .value("replacements")
+            .get()
+            .unwrap_or_default()
+    }
+    // Used from the next recording.
+    #[template_callback]
+    fn handle_save_replacements_btn_clicked(&self) {
+        let imp = self.imp();
+        let b = imp.replacements_view.buffer();
+        let text = b.text(&b.start_iter(), &b.end_iter(), false);
+        match Replacements::parse_rules(&text) {
+            Ok(rules) => {
+                imp.replacements_error.set_visible(false);
+                self.settings()
+                    .set_value("replacements", &rules.to_variant())
+                    .unwrap();
+            }
+            Err(e) => {
+                imp.replacements_error.set_label(&format!("{:#}", e));
+                imp.replacements_error.set_visible(true);
+            }
+        }
+    }
+    fn settings(&self) -> &gio::Settings {
+        self.imp()
+            .settings
+            .get_or_init(|| gio::Settings::new("com.ranfdev.Trascri"))
+    }
+    fn setup_settings(&self) {
+        let imp = self.imp();
+        let settings = self.settings();
+        settings
+            .bind("subtitle-line-length", &*imp.subtitle_line_length, "value")
+            .build();
+        settings
+            .bind(
+                "subtitle-max-cue-duration",
+                &*imp.subtitle_max_cue_duration,
+                "value",
+            )
+            .build();
+        settings
+            .bind(
+                "low-confidence-threshold",
+                &*imp.low_confidence_threshold,
+                "value",
+            )
+            .build();
+        settings.connect_changed(
+            Some("low-confidence-threshold"),
+            clone!(@weak self as obj => move |_, _| {
+                obj.mark_uncertain_words();
+            }),
+        );
+        settings
+            .bind(
+                "word-alternatives",
+                &*imp.word_alternatives_switch,
+                "active",
+            )
+            .build();
+        settings.connect_changed(
+            Some("word-alternatives"),
+            clone!(@weak self as obj => move |_, _| {
+                obj.reload_transcriber();
+            }),
+        );
+        settings
+            .bind("punctuation", &*imp.punctuation_switch, "active")
+            .build();
+        settings
+            .bind(
+                "normalize-numbers",
+                &*imp.normalize_numbers_switch,
+                "active",
+            )
+            .build();
+        imp.keywords_entry
+            .set_text(&settings.strv("keywords").join(", "));
+        settings
+            .bind("remove-filler-words", &*imp.fillers_switch, "active")
+            .build();
+        settings
+            .bind("mask-profanity", &*imp.profanity_switch, "active")
+            .build();
+        let replacements: Vec<String> = self
+            .replacements()
+            .iter()
+            .map(|(heard, written)| format!("{} -> {}", heard, written))
+            .collect();
+        imp.replacements_view
+            .buffer()
+            .set_text(&replacements.join("\n"));
+        settings
+            .bind(
+                "identify-speakers",
+                &*imp.identify_speakers_switch,
+                "active",
+            )
+            .build();
+        settings.connect_changed(
+            Some("identify-speakers"),
+            clone!(@weak self as obj => move |_, _| {
+                obj.update_speaker_model();
+            }),
+        );
+    }
+    // Recreates the transcriber with or without the speaker model, downloading
+    // it first if needed.
+    fn update_speaker_model(&self) {
+        let imp = self.imp();
+        let Some(models_repo) = imp.models_repo.borrow().clone() else {
+            return;
+        };
+        let row = imp.identify_speakers_row.clone();
+        row.set_subtitle("");
+        row.remove_css_class("error");
+
+        let speaker_model = ModelsRepo::speaker_model();
+        if !self.settings().boolean("identify-speakers")
+            || models_repo.is_downloaded(&speaker_model)
+        {
+            self.reload_transcriber();
+            return;
+        }
+        row.set_subtitle("Downloading the speaker model…");
+        imp.identify_speakers_switch.set_sensitive(false);
+        models_repo.download(
+            &speaker_model,
+            clone!(@weak row => move |p| {
+                row.set_subtitle(&format!(
+                    "Downloading the speaker model… {:.1} MB",
+                    p.read as f64 / 1e6
+                ));
+            }),
+            clone!(@weak self as obj, @weak row => move |res| {
+                obj.imp().identify_speakers_switch.set_sensitive(true);
+                match res {
+                    Ok(()) => {
+                        row.set_subtitle("");
+                        obj.reload_transcriber();
+                    }
+                    Err(e) => {
+                        obj.settings().set_boolean("identify-speakers", false).unwrap();
+                        let msg = format!("Download failed: {:#}", e);
+                        row.set_subtitle(&glib::markup_escape_text(&msg));
+                        row.add_css_class("error");
+                    }
                 }
-            });
-            transcriber.start(s);
+            }),
+        );
+    }
+    // Recreates the transcriber, to apply changed recognizer settings.
+    fn reload_transcriber(&self) {
+        if self.imp().transcriber.borrow().is_some() {
+            self.load_transcriber();
+        }
+    }
+    fn command_profiles(&self) -> HashMap<String, Vec<String>> {
+        self.settings()
+            .value("command-profiles")
+            .get()
+            .unwrap_or_default()
+    }
+    fn set_command_profiles(&self, profiles: &HashMap<String, Vec<String>>) {
+        self.settings()
+            .set_value("command-profiles", &profiles.to_variant())
+            .unwrap();
+    }
+    fn vocabulary(&self) -> Vocabulary {
+        let settings = self.settings();
+        if !settings.boolean("command-mode") {
+            return Vocabulary::Open;
         }
+        match self
+            .command_profiles()
+            .remove(settings.string("command-profile").as_str())
+        {
+            Some(phrases) if !phrases.is_empty() => Vocabulary::Phrases(phrases),
+            _ => Vocabulary::Open,
+        }
+    }
+    fn setup_command_mode(&self) {
+        let imp = self.imp();
+        let settings = self.settings();
+        settings
+            .bind("command-mode", &*imp.command_mode_row, "enable-expansion")
+            .build();
+        for key in ["command-mode", "command-profile"] {
+            settings.connect_changed(
+                Some(key),
+                clone!(@weak self as obj => move |_, _| {
+                    obj.reload_transcriber();
+                }),
+            );
+        }
+        imp.command_profile_row.connect_selected_item_notify(
+            clone!(@weak self as obj => move |row| {
+                let name = row
+                    .selected_item()
+                    .and_then(|item| item.downcast::<gtk::StringObject>().ok())
+                    .map(|item| item.string().to_string())
+                    .unwrap_or_default();
+                if obj.settings().string("command-profile") != name {
+                    obj.settings().set_string("command-profile", &name).unwrap();
+                }
+                obj.show_command_phrases();
+            }),
+        );
+        self.refresh_command_profiles();
+    }
+    // Lists the saved profiles, selecting the active one.
+    fn refresh_command_profiles(&self) {
+        let imp = self.imp();
+        let mut names: Vec<String> = self.command_profiles().into_keys().collect();
+        names.sort();
+        let active = self.settings().string("command-profile");
+        let model = gtk::StringList::new(&names.iter().map(|n| n.as_str()).collect::<Vec<_>>());
+        imp.command_profile_row.set_model(Some(&model));
+        if let Some(i) = names.iter().position(|n| *n == active) {
+            imp.command_profile_row.set_selected(i as u32);
+        }
+        self.show_command_phrases();
+    }
+    fn show_command_phrases(&self) {
+        let imp = self.imp();
+        let active = self.settings().string("command-profile");
+        let phrases = self.command_profiles().remove(active.as_str());
+        imp.command_phrases_view
+            .buffer()
+            .set_text(&phrases.as_deref().unwrap_or_default().join("\n"));
+        imp.command_phrases_view.set_sensitive(phrases.is_some());
+        imp.save_phrases_btn.set_sensitive(phrases.is_some());
+        imp.delete_profile_btn.set_sensitive(phrases.is_some());
+    }
+    // Used from the next recording.
+    #[template_callback]
+    fn handle_keywords_apply(&self) {
+        let text = self.imp().keywords_entry.text();
+        let keywords: Vec<&str> = text
+            .split(',')
+            .map(str::trim)
+            .filter(|k| !k.is_empty())
+            .collect();
+        self.settings().set_strv("keywords", &keywords).unwrap();
+    }
+    #[template_callback]
+    fn handle_new_profile_apply(&self) {
+        let imp = self.imp();
+        let name = imp.new_profile_row.text().trim().to_owned();
+        if name.is_empty() {
+            return;
+        }
+        let mut profiles = self.command_profiles();
+        profiles.entry(name.clone()).or_default();
+        self.set_command_profiles(&profiles);
+        self.settings()
+            .set_string("command-profile", &name)
+            .unwrap();
+        imp.new_profile_row.set_text("");
+        self.refresh_command_profiles();
+    }
+    #[template_callback]
+    fn handle_delete_profile_btn_clicked(&self) {
+        let mut profiles = self.command_profiles();
+        profiles.remove(self.settings().string("command-profile").as_str());
+        self.set_command_profiles(&profiles);
+        let mut names: Vec<&String> = profiles.keys().collect();
+        names.sort();
+        let next = names.first().map_or("", |n| n.as_str());
+        self.settings().set_string("command-profile", next).unwrap();
+        self.refresh_command_profiles();
+    }
+    #[template_callback]
+    fn handle_save_phrases_btn_clicked(&self) {
+        let b = self.imp().command_phrases_view.buffer();
+        let text = b.text(&b.start_iter(), &b.end_iter(), false);
+        let phrases = text
+            .lines()
+            .map(str::trim)
+            .filter(|l| !l.is_empty())
+            .map(str::to_owned)
+            .collect();
+
+        let mut profiles = self.command_profiles();
+        profiles.insert(self.settings().string("command-profile").into(), phrases);
+        self.set_command_profiles(&profiles);
+        if self.settings().boolean("command-mode") {
+            self.reload_transcriber();
+        }
+    }
+    fn export_subtitles(&self) {
+        let segments = self.imp().segments.borrow().clone();
+        let settings = self.settings();
+        let opts = CueOptions {
+            max_line_len: settings.uint("subtitle-line-length") as usize,
+            max_duration: settings.double("subtitle-max-cue-duration") as f32,
+            ..Default::default()
+        };
+
+        let obj = self.clone();
+        glib::MainContext::default().spawn_local(async move {
+            let dialog = gtk::FileChooserNative::new(
+                Some("Export subtitles"),
+                Some(&obj),
+                gtk::FileChooserAction::Save,
+                None,
+                None,
+            );
+            let srt_filter = gtk::FileFilter::new();
+            srt_filter.set_name(Some("SubRip (.srt)"));
+            srt_filter.add_suffix("srt");
+            let vtt_filter = gtk::FileFilter::new();
+            vtt_filter.set_name(Some("WebVTT (.vtt)"));
+            vtt_filter.add_suffix("vtt");
+            dialog.add_filter(&srt_filter);
+            dialog.add_filter(&vtt_filter);
+            dialog.set_current_name("transcript.srt");
+
+            if dialog.run_future().await != gtk::ResponseType::Accept {
+                return;
+            }
+            let Some(file) = dialog.file() else {
+                return;
+            };
+
+            let format = file
+                .path()
+                .and_then(|p| p.extension().map(|e| e.to_string_lossy().into_owned()))
+                .and_then(|ext| subtitles::Format::from_extension(&ext))
+                .unwrap_or(if dialog.filter().as_ref() == Some(&vtt_filter) {
+                    subtitles::Format::WebVtt
+                } else {
+                    subtitles::Format::Srt
+                });
+            let contents = subtitles::write(format, &subtitles::cues(&segments, &opts));
+            if let Err((_, e)) = file
+                .replace_contents_future(
+                    contents,
+                    None,
+                    false,
+                    gio::FileCreateFlags::REPLACE_DESTINATION,
+                )
+                .await
+            {
+                obj.show_message(&format!("Couldn't export the subtitles: {}", e));
+            }
+        });
+    }
+    fn setup_text_tags(&self) {
+        self.imp()
+            .text_view
+            .buffer()
+            .create_tag(Some("speaker"), &[("weight", &700)]);
+        self.imp().text_view.buffer().create_tag(
+            Some("uncertain"),
+            &[("underline", &pango::Underline::Error)],
+        );
+        self.imp().text_view.buffer().create_tag(
+            Some("keyword"),
+            &[("background", &"rgba(246, 211, 45, 0.5)")],
+        );
     }
     fn setup_css(&self) {
         let provider = gtk::CssProvider::new();
@@ -491,6 +1824,11 @@ window.subtitle-mode {
 
         obj.setup_language_chooser(glib::user_data_dir().join("models"));
         obj.setup_drop_down();
+        obj.setup_drop_target();
+        obj.setup_word_alternatives();
+        obj.setup_text_tags();
+        obj.setup_settings();
+        obj.setup_command_mode();
 
         obj.setup_css();
         obj.set_subtitle_mode(false);
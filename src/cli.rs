@@ -0,0 +1,283 @@
+// Headless transcription, without any GTK window.
+// Usage: trascri --headless --model <name|path> [--second-model <name|path>]
+//                [--input <file|device>] [--format text|json|srt]
+//                [--speakers] [--punctuate] [--normalize-numbers] [--phrases <file>]
+//                [--keyword <phrase>]...
+//                [--replacements <file>] [--remove-fillers] [--mask-profanity]
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::thread;
+
+use anyhow::{anyhow, bail, Context};
+use gst::prelude::DeviceExt;
+use gtk::prelude::*;
+use gtk::{gio, glib};
+use postage::mpsc;
+use postage::prelude::*;
+
+use crate::adapters::audio_src::file::File;
+use crate::adapters::audio_src::pulse::Pulse;
+use crate::adapters::models_repo::{Engine, ModelsRepo};
+use crate::adapters::recognizer::vosk;
+use crate::adapters::text_filter::fillers::Fillers;
+use crate::adapters::text_filter::profanity::Profanity;
+use crate::adapters::text_filter::replacements::Replacements;
+use crate::app::subtitles::{self, CueOptions};
+use crate::app::transcriber::{Msg, SessionOptions, TranscriberActor};
+use crate::app::SAMPLE_RATE;
+use crate::ports::{AudioSrc, TextFilter, Vocabulary};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OutputFormat {
+    Text,
+    Json,
+    Srt,
+}
+
+#[derive(Debug)]
+pub struct Args {
+    model: String,
+    // Makes the session bilingual.
+    second_model: Option<String>,
+    input: Option<String>,
+    format: OutputFormat,
+    speakers: bool,
+    punctuate: bool,
+    normalize_numbers: bool,
+    // A file with one phrase per line, the only ones to recognize.
+    phrases: Option<PathBuf>,
+    // Hits are reported on stderr.
+    keywords: Vec<String>,
+    // A file with one "heard -> written" rule per line.
+    replacements: Option<PathBuf>,
+    remove_fillers: bool,
+    mask_profanity: bool,
+}
+
+impl Args {
+    // Returns `None` when the app wasn't launched in headless mode.
+    pub fn parse(args: &[String]) -> Option<anyhow::Result<Self>> {
+        if !args.iter().any(|a| a == "--headless") {
+            return None;
+        }
+        Some(Self::parse_headless(args))
+    }
+    fn parse_headless(args: &[String]) -> anyhow::Result<Self> {
+        let mut model = None;
+        let mut second_model = None;
+        let mut input = None;
+        let mut format = OutputFormat::Text;
+        let mut speakers = false;
+        let mut punctuate = false;
+        let mut normalize_numbers = false;
+        let mut phrases = None;
+        let mut keywords = vec![];
+        let mut replacements = None;
+        let mut remove_fillers = false;
+        let mut mask_profanity = false;
+
+        let mut iter = args.iter().skip(1);
+        while let Some(arg) = iter.next() {
+            let mut value = || {
+                iter.next()
+                    .cloned()
+                    .ok_or_else(|| anyhow!("missing value for {}", arg))
+            };
+            match arg.as_str() {
+                "--headless" => {}
+                "--model" => model = Some(value()?),
+                "--second-model" => second_model = Some(value()?),
+                "--input" => input = Some(value()?),
+                "--speakers" => speakers = true,
+                "--punctuate" => punctuate = true,
+                "--normalize-numbers" => normalize_numbers = true,
+                "--phrases" => phrases = Some(PathBuf::from(value()?)),
+                "--keyword" => keywords.push(value()?),
+                "--replacements" => replacements = Some(PathBuf::from(value()?)),
+                "--remove-fillers" => remove_fillers = true,
+                "--mask-profanity" => mask_profanity = true,
+                "--format" => {
+                    format = match value()?.as_str() {
+                        "text" => OutputFormat::Text,
+                        "json" => OutputFormat::Json,
+                        "srt" => OutputFormat::Srt,
+                        other => bail!("unknown format {:?}, expected text, json or srt", other),
+                    }
+                }
+                other => bail!("unknown argument {:?}", other),
+            }
+        }
+
+        Ok(Self {
+            model: model.context("--model is required")?,
+            second_model,
+            input,
+            format,
+            speakers,
+            punctuate,
+            normalize_numbers,
+            phrases,
+            keywords,
+            replacements,
+            remove_fillers,
+            mask_profanity,
+        })
+    }
+}
+
+fn model_path(model: &str) -> anyhow::Result<PathBuf> {
+    if Path::new(model).is_dir() {
+        return Ok(PathBuf::from(model));
+    }
+    let models_repo = ModelsRepo::from_path(glib::user_data_dir().join("models"));
+    // Downloaded and imported models.
+    let local_path = models_repo.local_model_path(model);
+    if local_path.is_dir() {
+        return Ok(local_path);
+    }
+    let remote_model = ModelsRepo::models_iter()
+        .find(|m| m.name == model)
+        .with_context(|| format!("unknown model {:?}", model))?;
+    bail!(
+        "model {:?} is not downloaded, install it from the app first",
+        remote_model.name
+    );
+}
+
+fn speaker_model_path() -> anyhow::Result<PathBuf> {
+    let models_repo = ModelsRepo::from_path(glib::user_data_dir().join("models"));
+    let speaker_model = ModelsRepo::speaker_model();
+    if !models_repo.is_downloaded(&speaker_model) {
+        bail!("the speaker model is not downloaded, enable \"Identify speakers\" in the app first");
+    }
+    Ok(models_repo.model_path(&speaker_model))
+}
+
+// An existing path is transcribed as a file, anything else is matched against
+// the names of the available audio sources.
+fn input_element(input: &str) -> anyhow::Result<gst::Element> {
+    if Path::new(input).exists() {
+        return Ok(File::from(gio::File::for_path(input)).make_element());
+    }
+    let devices = Pulse::list_available();
+    (0..devices.n_items())
+        .filter_map(|i| devices.item(i)?.downcast::<gst::Device>().ok())
+        .find(|d| d.display_name().contains(input))
+        .map(|d| Pulse::from(d).make_element())
+        .with_context(|| format!("no file or audio device named {:?}", input))
+}
+
+// In the same order as the app.
+fn text_filters(args: &Args) -> anyhow::Result<Vec<Arc<dyn TextFilter>>> {
+    let mut filters: Vec<Arc<dyn TextFilter>> = vec![];
+    if let Some(ref path) = args.replacements {
+        let text = std::fs::read_to_string(path)
+            .with_context(|| format!("reading replacements from {}", path.display()))?;
+        let rules = Replacements::parse_rules(&text)
+            .with_context(|| format!("parsing replacements in {}", path.display()))?;
+        filters.push(Arc::new(Replacements::new(&rules)));
+    }
+    if args.remove_fillers {
+        filters.push(Arc::new(Fillers));
+    }
+    if args.mask_profanity {
+        filters.push(Arc::new(Profanity));
+    }
+    Ok(filters)
+}
+
+pub fn run(args: Args) -> anyhow::Result<()> {
+    let filters = text_filters(&args)?;
+    let vocabulary = match args.phrases {
+        Some(ref path) => {
+            let text = std::fs::read_to_string(path)
+                .with_context(|| format!("reading phrases from {}", path.display()))?;
+            let phrases: Vec<String> = text
+                .lines()
+                .map(str::trim)
+                .filter(|l| !l.is_empty())
+                .map(str::to_owned)
+                .collect();
+            if phrases.is_empty() {
+                bail!("no phrases in {}", path.display());
+            }
+            Vocabulary::Phrases(phrases)
+        }
+        None => Vocabulary::Open,
+    };
+    let opts = vosk::Options {
+        // Nothing shows the alternatives here.
+        max_alternatives: 0,
+        speaker_model_path: args.speakers.then(speaker_model_path).transpose()?,
+        vocabulary,
+    };
+
+    let (rms_s, mut rms_r) = mpsc::channel::<f64>(10);
+    thread::spawn(move || while rms_r.blocking_recv().is_some() {});
+
+    // With "auto", the language is detected among the installed vosk models.
+    let transcriber = if let Some(ref second_model) = args.second_model {
+        if args.model == "auto" {
+            bail!("--second-model can't be used with --model auto");
+        }
+        let paths = [model_path(&args.model)?, model_path(second_model)?];
+        TranscriberActor::bilingual(paths, SAMPLE_RATE as f32, opts, rms_s)?
+    } else if args.model == "auto" {
+        let models_repo = ModelsRepo::from_path(glib::user_data_dir().join("models"));
+        let paths = models_repo.installed_models(Engine::Vosk)?;
+        let (detected_s, mut detected_r) = mpsc::channel::<PathBuf>(1);
+        thread::spawn(move || {
+            while let Some(path) = detected_r.blocking_recv() {
+                eprintln!("trascri: detected {}", path.display());
+            }
+        });
+        TranscriberActor::auto(paths, SAMPLE_RATE as f32, opts, rms_s, detected_s)?
+    } else {
+        let path = model_path(&args.model)?;
+        TranscriberActor::for_model(&path, SAMPLE_RATE as f32, opts, rms_s)?
+    };
+    if let Some(ref input) = args.input {
+        transcriber.set_element(input_element(input)?);
+    }
+
+    let (s, mut r) = mpsc::channel(8);
+    let session = SessionOptions {
+        punctuation: args.punctuate,
+        normalize_numbers: args.normalize_numbers,
+        keywords: args.keywords,
+        filters,
+    };
+    transcriber.start(s, session);
+
+    let mut segments = vec![];
+    let mut speaker = None;
+    while let Some(msg) = r.blocking_recv() {
+        match msg {
+            Msg::Result(segment) if !segment.words.is_empty() => match args.format {
+                OutputFormat::Text => {
+                    match segment.speaker.filter(|s| Some(*s) != speaker) {
+                        Some(s) => println!("Speaker {}: {}", s + 1, segment.text()),
+                        None => println!("{}", segment.text()),
+                    }
+                    speaker = segment.speaker.or(speaker);
+                }
+                OutputFormat::Json => println!("{}", serde_json::to_string(&segment)?),
+                OutputFormat::Srt => segments.push(segment),
+            },
+            Msg::KeywordHit(hit) => eprintln!(
+                "trascri: heard {:?} at {:.1}s: {}",
+                hit.keyword, hit.start, hit.context
+            ),
+            Msg::Error(e) => return Err(e),
+            Msg::Stopped => break,
+            _ => (),
+        }
+    }
+
+    if args.format == OutputFormat::Srt {
+        let cues = subtitles::cues(&segments, &CueOptions::default());
+        print!("{}", subtitles::write(subtitles::Format::Srt, &cues));
+    }
+    Ok(())
+}
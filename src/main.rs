@@ -20,6 +20,7 @@
 
 mod adapters;
 mod app;
+mod cli;
 mod config;
 mod ports;
 
@@ -30,6 +31,17 @@ use gtk::gio;
 use gtk::prelude::*;
 
 fn main() {
+    gst::init().unwrap();
+
+    let args: Vec<String> = std::env::args().collect();
+    if let Some(args) = cli::Args::parse(&args) {
+        if let Err(e) = args.and_then(cli::run) {
+            eprintln!("trascri: {:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+
     // Set up gettext translations
     bindtextdomain(GETTEXT_PACKAGE, LOCALEDIR).expect("Unable to bind the text domain");
     bind_textdomain_codeset(GETTEXT_PACKAGE, "UTF-8")
@@ -41,12 +53,10 @@ fn main() {
         .expect("Could not load resources");
     gio::resources_register(&resources);
 
-    gst::init().unwrap();
-
     // Create a new GtkApplication. The application manages our main loop,
     // application windows, integration with the window manager/compositor, and
     // desktop features such as file opening and single-instance applications.
-    let app = TrascriApplication::new("com.ranfdev.Trascri", &gio::ApplicationFlags::empty());
+    let app = TrascriApplication::new("com.ranfdev.Trascri", &gio::ApplicationFlags::HANDLES_OPEN);
 
     // Run the application. This function will block until the application
     // exits. Upon return, we have our exit code to return to the shell. (This
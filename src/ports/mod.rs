@@ -1,4 +1,6 @@
 pub mod audio_src;
 pub mod recognizer;
+pub mod text_filter;
 pub use audio_src::*;
 pub use recognizer::*;
+pub use text_filter::*;
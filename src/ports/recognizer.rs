@@ -1,10 +1,144 @@
+use serde::Serialize;
+
 pub struct Word<'a> {
+    // Acoustic, from 0.0 to 1.0. Recognizers giving alternatives may only be
+    // able to tell how many of them agree on the word.
     pub confidence: f32,
+    // Times are in seconds, relative to the start of the stream.
+    pub start: f32,
+    pub end: f32,
     pub text: &'a str,
 }
 
+// An owned word, kept after the recognizer moves on.
+#[derive(Debug, Clone, Serialize)]
+pub struct SegmentWord {
+    pub text: String,
+    pub confidence: f32,
+    pub start: f32,
+    pub end: f32,
+    // Part of a phrase of the watch list.
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    pub keyword: bool,
+}
+
+impl From<Word<'_>> for SegmentWord {
+    fn from(w: Word<'_>) -> Self {
+        Self {
+            text: w.text.to_owned(),
+            confidence: w.confidence,
+            start: w.start,
+            end: w.end,
+            keyword: false,
+        }
+    }
+}
+
+impl SegmentWord {
+    // One word spanning the times of `words`, keeping the punctuation after
+    // them. It's as uncertain as the most uncertain of them.
+    pub fn merge(words: &[SegmentWord], text: &str) -> Self {
+        let last = &words[words.len() - 1];
+        Self {
+            text: format!("{}{}", text, trailing_punctuation(&last.text)),
+            confidence: words
+                .iter()
+                .map(|w| w.confidence)
+                .fold(f32::INFINITY, f32::min),
+            start: words[0].start,
+            end: last.end,
+            keyword: words.iter().any(|w| w.keyword),
+        }
+    }
+}
+
+// The word without the punctuation around it, lowercase, to match words
+// however they're written.
+pub fn normalize_word(word: &str) -> String {
+    word.trim_matches(|c: char| !c.is_alphanumeric())
+        .to_lowercase()
+}
+
+// The punctuation after the last letter, like "," or "?".
+pub fn trailing_punctuation(word: &str) -> &str {
+    let end = word.trim_end_matches(|c: char| !c.is_alphanumeric()).len();
+    &word[end..]
+}
+
+// A competing hypothesis for the same stretch of audio.
+pub struct Alternative<'a> {
+    // Only comparable with the scores of the other alternatives of the same result.
+    pub score: f32,
+    pub words: Vec<Word<'a>>,
+}
+
 pub struct Recognized<'a> {
     pub words: Vec<Word<'a>>,
+    pub start: f32,
+    pub end: f32,
+    // The hypotheses that lost against `words`, from the most to the least likely.
+    pub alternatives: Vec<Alternative<'a>>,
+    // A voiceprint of who spoke, comparable with the cosine similarity. Only
+    // set by recognizers that can tell speakers apart.
+    pub speaker: Option<Vec<f32>>,
+}
+
+impl<'a> Recognized<'a> {
+    pub fn new(words: Vec<Word<'a>>) -> Self {
+        let start = words.first().map_or(0.0, |w| w.start);
+        let end = words.last().map_or(start, |w| w.end);
+        Self {
+            words,
+            start,
+            end,
+            alternatives: vec![],
+            speaker: None,
+        }
+    }
+    pub fn with_alternatives(mut self, alternatives: Vec<Alternative<'a>>) -> Self {
+        self.alternatives = alternatives;
+        self
+    }
+    pub fn with_speaker(mut self, speaker: Option<Vec<f32>>) -> Self {
+        self.speaker = speaker;
+        self
+    }
+}
+
+// What a recognizer listens for.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Vocabulary {
+    #[default]
+    Open,
+    // Only these phrases, anything else is recognized as unknown.
+    Phrases(Vec<String>),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SampleFormat {
+    I16,
+    F32,
+}
+
+// A mono PCM sample that recognizers can be fed with.
+pub trait Sample: Copy + Send + 'static {
+    const FORMAT: SampleFormat;
+    // Scaled to the -1.0..=1.0 range.
+    fn to_f64(self) -> f64;
+}
+
+impl Sample for i16 {
+    const FORMAT: SampleFormat = SampleFormat::I16;
+    fn to_f64(self) -> f64 {
+        f64::from(self) / f64::from(i16::MAX)
+    }
+}
+
+impl Sample for f32 {
+    const FORMAT: SampleFormat = SampleFormat::F32;
+    fn to_f64(self) -> f64 {
+        f64::from(self)
+    }
 }
 
 #[derive(PartialEq, Eq)]
@@ -14,10 +148,58 @@ pub enum DecodingState {
 }
 
 pub trait Recognizer {
-    type Sample;
-    fn feed(&mut self, data: &[Self::Sample]) -> DecodingState;
-    fn partial_result(&mut self) -> anyhow::Result<Recognized>;
-    fn result(&mut self) -> anyhow::Result<Recognized>;
+    type Sample: Sample;
+    fn feed(&mut self, data: &[Self::Sample]) -> anyhow::Result<DecodingState>;
+    fn partial_result(&mut self) -> anyhow::Result<Recognized<'_>>;
+    fn result(&mut self) -> anyhow::Result<Recognized<'_>>;
+    // Flushes the audio fed so far, to be called at the end of the stream.
+    fn final_result(&mut self) -> anyhow::Result<Recognized<'_>>;
     fn sample_rate(&self) -> f32;
+    // Code of the language recognized, like "en", when it's known.
+    fn language(&self) -> Option<&str>;
     fn reset(&mut self);
 }
+
+#[cfg(test)]
+pub mod fixtures {
+    use super::SegmentWord;
+
+    fn word(text: &str, start: f32, end: f32) -> SegmentWord {
+        SegmentWord {
+            text: text.to_owned(),
+            confidence: 1.0,
+            start,
+            end,
+            keyword: false,
+        }
+    }
+
+    // One word per second, starting at `start`.
+    pub fn words(text: &str, start: f32) -> Vec<SegmentWord> {
+        text.split_whitespace()
+            .enumerate()
+            .map(|(i, text)| word(text, start + i as f32, start + i as f32 + 0.5))
+            .collect()
+    }
+
+    // Words with the pause after each of them, in seconds.
+    pub fn paced_words(words: &[(&str, f32)]) -> Vec<SegmentWord> {
+        let mut time = 0.0;
+        words
+            .iter()
+            .map(|&(text, pause)| {
+                let w = word(text, time, time + 0.3);
+                time = w.end + pause;
+                w
+            })
+            .collect()
+    }
+
+    pub fn text(words: &[SegmentWord]) -> String {
+        words
+            .iter()
+            .map(|w| w.text.as_str())
+            .collect::<Vec<&str>>()
+            .join(" ")
+    }
+}
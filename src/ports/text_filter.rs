@@ -0,0 +1,11 @@
+use std::fmt::Debug;
+
+use super::SegmentWord;
+
+// Rewrites the words of the results before they're shown, like fixing the
+// spelling of names or hiding some words. Filters run in order, on partial
+// and final results alike.
+pub trait TextFilter: Debug + Send + Sync {
+    // Words can be removed or merged, the ones left keep their times.
+    fn filter(&self, words: &mut Vec<SegmentWord>);
+}